@@ -0,0 +1,88 @@
+//! Combining several readings into one robust value.
+
+
+/// How several readings are combined.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Strategy {
+    Mean,
+    /// Middle reading, or the mean of the two middle ones; rejects outliers.
+    Median,
+    /// Mean after dropping the `trim` lowest and `trim` highest readings.
+    /// At least one reading is always kept.
+    TrimmedMean { trim: usize },
+}
+
+
+/// Combined value of several readings, with their spread.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Aggregate {
+    pub value: f32,
+    /// Lowest reading.
+    pub min: f32,
+    /// Highest reading.
+    pub max: f32,
+    /// Number of readings taken.
+    pub count: usize,
+}
+
+impl Aggregate {
+    /// Combine `readings`, which are sorted in place. `None` if empty.
+    pub fn from_readings(readings: &mut [f32], strategy: Strategy) -> Option<Self> {
+        readings.sort_unstable_by(f32::total_cmp);
+        let (&min, &max) = (readings.first()?, readings.last()?);
+        let count = readings.len();
+
+        let value = match strategy {
+            Strategy::Mean => mean(readings),
+            Strategy::Median if count.is_multiple_of(2) => (readings[count / 2 - 1] + readings[count / 2]) / 2.0,
+            Strategy::Median => readings[count / 2],
+            Strategy::TrimmedMean { trim } => {
+                let trim = trim.min((count - 1) / 2);
+                mean(&readings[trim..count - trim])
+            },
+        };
+
+        Some(Aggregate { value, min, max, count })
+    }
+
+    /// Difference between the highest and lowest reading.
+    pub fn spread(&self) -> f32 {
+        self.max - self.min
+    }
+}
+
+
+fn mean(readings: &[f32]) -> f32 {
+    readings.iter().sum::<f32>() / readings.len() as f32
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strategies() {
+        let readings = [10.0, 12.0, 11.0, 400.0, 9.0];
+
+        let mean = Aggregate::from_readings(&mut readings.clone(), Strategy::Mean).unwrap();
+        assert_eq!(mean.value, 88.4);
+        assert_eq!(mean.spread(), 391.0);
+
+        let median = Aggregate::from_readings(&mut readings.clone(), Strategy::Median).unwrap();
+        assert_eq!(median.value, 11.0);
+        assert_eq!((median.min, median.max, median.count), (9.0, 400.0, 5));
+
+        let trimmed = Aggregate::from_readings(&mut readings.clone(), Strategy::TrimmedMean { trim: 1 }).unwrap();
+        assert_eq!(trimmed.value, 11.0);
+        let trimmed = Aggregate::from_readings(&mut readings.clone(), Strategy::TrimmedMean { trim: 9 }).unwrap();
+        assert_eq!(trimmed.value, 11.0);
+
+        assert_eq!(Aggregate::from_readings(&mut [1.0, 2.0], Strategy::Median).unwrap().value, 1.5);
+        assert_eq!(Aggregate::from_readings(&mut [], Strategy::Mean), None);
+    }
+}
@@ -0,0 +1,393 @@
+//! Async counterpart of [`LightSensor`](crate::LightSensor), built on `embedded-hal-async`.
+
+// Public imports
+use embedded_hal::digital;
+use embedded_hal_async::{delay::DelayNs, digital::Wait, i2c::I2c};
+
+// Local imports
+use crate::{i2c, Address, Aggregate, Calibration, ChipVariant, Config, ConfigBuilder, LightSensorError, Measurement, PowerPlan, Resolution, ScanResult, Strategy, ThresholdEvent, Veml7700};
+use i2c::constants;
+
+
+/// Shorthand for [`Config::builder`], callable without naming the bus types.
+impl LightSensor<(), ()> {
+    /// Start building a [`Config`] for [`apply_config`](LightSensor::apply_config).
+    pub fn builder() -> ConfigBuilder {
+        Config::builder()
+    }
+}
+
+pub struct LightSensor<I2C, Delay, V = Veml7700> {
+    dev: i2c::asynch::LightSensorI2c<I2C, Delay, V>,
+    calibration: Calibration,
+    /// Settings changed without a full refresh time since.
+    settings_changed: bool,
+}
+
+impl<I2C: I2c, Delay: DelayNs> LightSensor<I2C, Delay> {
+    pub fn new(dev: I2C, address: Address, delay: Delay) -> Self {
+        Self::with_variant(dev, address, delay, Veml7700)
+    }
+
+    pub async fn build(dev: I2C, address: Address, delay: Delay) -> Result<LightSensor<I2C, Delay>, LightSensorError<I2C::Error>> {
+        let mut sensor = Self::new(dev, address, delay);
+        sensor.apply_config(&Config::default()).await?;
+
+        Ok(sensor)
+    }
+}
+
+impl<I2C: I2c, Delay: DelayNs, V: ChipVariant> LightSensor<I2C, Delay, V> {
+    /// Create a LightSensor for the part `V`.
+    pub fn with_variant(dev: I2C, address: Address, delay: Delay, variant: V) -> Self {
+        let i2c_wrapper = i2c::asynch::LightSensorI2c::with_variant(dev, address.into(), delay, variant);
+        LightSensor{dev: i2c_wrapper, calibration: Calibration::for_variant::<V>(), settings_changed: true}
+    }
+
+    /// Write a whole configuration, one write per register.
+    ///
+    /// Thresholds and power saving go first so the sensor never runs with a
+    /// stale window; the configuration register, which powers it on, goes last.
+    pub async fn apply_config(&mut self, config: &Config) -> Result<(), LightSensorError<I2C::Error>> {
+        self.settings_changed = true;
+        self.dev.write_word(constants::registers::H_THRESH_REG, config.high_threshold).await?;
+        self.dev.write_word(constants::registers::L_THRESH_REG, config.low_threshold).await?;
+        self.dev.write_word(constants::registers::POWER_SAVE_REG, config.power_save_word()).await?;
+        self.dev.write_word(constants::registers::SETTING_REG, config.setting_word::<V>()).await?;
+
+        if config.shutdown == i2c::Shutdown::PowerOn {
+            self.dev.delay_ms(i2c::POWER_ON_DELAY_MS).await;
+        }
+
+        Ok(())
+    }
+
+    /// Read the whole configuration back from the device.
+    pub async fn read_config(&mut self) -> Result<Config, LightSensorError<I2C::Error>> {
+        let setting = self.dev.read_word(constants::registers::SETTING_REG).await?;
+        let power_save = self.dev.read_word(constants::registers::POWER_SAVE_REG).await?;
+        let high_threshold = self.dev.read_word(constants::registers::H_THRESH_REG).await?;
+        let low_threshold = self.dev.read_word(constants::registers::L_THRESH_REG).await?;
+
+        Ok(Config::from_words::<V>(setting, power_save, high_threshold, low_threshold)?)
+    }
+
+    /// Cache the configuration registers in the driver, so reads such as
+    /// [`convert_raw_to_lux`](Self::convert_raw_to_lux) stay off the bus.
+    pub async fn sync_from_device(&mut self) -> Result<(), LightSensorError<I2C::Error>> {
+        Ok(self.dev.sync_from_device().await?)
+    }
+
+    /// Drop the cached configuration registers.
+    pub fn invalidate(&mut self) {
+        self.dev.invalidate();
+    }
+
+    /// Address the sensor is bound to.
+    pub fn address(&self) -> u8 {
+        self.dev.address()
+    }
+
+    /// Shut the sensor down until the next configuration write powers it on.
+    pub async fn power_off(&mut self) -> Result<(), LightSensorError<I2C::Error>> {
+        self.dev.set_shutdown(i2c::Shutdown::PowerOff).await?;
+        self.settings_changed = true;
+
+        Ok(())
+    }
+
+    /// Give back the bus and the delay, leaving the sensor as it is.
+    ///
+    /// Use [`destroy_powered_off`](Self::destroy_powered_off) to leave it shut down.
+    pub fn destroy(self) -> (I2C, Delay) {
+        self.dev.destroy()
+    }
+
+    /// Shut the sensor down, then give back the bus and the delay.
+    ///
+    /// On a bus error the driver is handed back along with it.
+    pub async fn destroy_powered_off(mut self) -> i2c::Destroyed<I2C, Delay, Self, LightSensorError<I2C::Error>> {
+        match self.power_off().await {
+            Ok(()) => Ok(self.destroy()),
+            Err(error) => Err((self, error)),
+        }
+    }
+
+    /// Read the ID register.
+    pub async fn identify(&mut self) -> Result<i2c::DeviceId, LightSensorError<I2C::Error>> {
+        Ok(self.dev.read_device_id().await?)
+    }
+
+    /// Check that the part `V` answers at the configured address.
+    pub async fn probe(&mut self) -> Result<i2c::DeviceId, LightSensorError<I2C::Error>> {
+        let id = self.identify().await?;
+        if id.device != V::DEVICE_ID {
+            return Err(LightSensorError::WrongDevice { id: id.device });
+        }
+
+        Ok(id)
+    }
+
+    /// Scale of the active gain and integration time, for converting
+    /// counts without further bus traffic.
+    pub async fn resolution(&mut self) -> Result<Resolution<V>, LightSensorError<I2C::Error>> {
+        let gain = self.dev.get_gain().await?;
+        let integration_time = self.dev.get_integration_time().await?;
+
+        Ok(Resolution::for_variant(gain, integration_time).with_calibration(self.calibration))
+    }
+
+    /// Replace the calibration applied to every lux result.
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.calibration = calibration;
+    }
+
+    pub fn get_calibration(&self) -> Calibration {
+        self.calibration
+    }
+
+    pub async fn convert_raw_to_lux(&mut self, raw: u16) -> Result<f32, LightSensorError<I2C::Error>> {
+        Ok(self.resolution().await?.lux(raw))
+    }
+
+    pub async fn get_ambient_light_lux(&mut self) -> Result<f32, LightSensorError<I2C::Error>> {
+        let raw_lux = self.dev.get_ambient_light_output().await?;
+
+        let lux = self.convert_raw_to_lux(raw_lux).await?;
+        Ok(lux)
+    }
+
+    pub async fn get_white_light(&mut self) -> Result<f32, LightSensorError<I2C::Error>> {
+        let white_light = self.dev.get_white_light_output().await?;
+
+        Ok(white_light as f32)
+    }
+
+    /// Set the integration time and power-saving mode chosen by a [`PowerPlan`].
+    pub async fn apply_power_plan(&mut self, plan: &PowerPlan) -> Result<(), LightSensorError<I2C::Error>> {
+        self.dev.set_integration_time(plan.integration_time).await?;
+        if let Some(mode) = plan.mode {
+            self.dev.set_power_saving_mode(mode).await?;
+        }
+        self.dev.set_power_saving_mode_enabled(plan.power_saving_mode_enable()).await?;
+        self.settings_changed = true;
+
+        Ok(())
+    }
+
+    /// Time between two samples under the current settings.
+    pub async fn refresh_time_ms(&mut self) -> Result<u32, LightSensorError<I2C::Error>> {
+        let integration_time = self.dev.get_integration_time().await?;
+        let mode = self.dev.get_power_saving_mode().await?;
+        let enable = self.dev.get_power_saving_mode_enabled().await?;
+
+        Ok(i2c::refresh_time_ms(integration_time, mode, enable))
+    }
+
+    /// Measure, first waiting one [refresh time](Self::refresh_time_ms) if
+    /// the settings changed since the last full refresh time, so the
+    /// counts never belong to the previous settings.
+    pub async fn read_fresh(&mut self) -> Result<Measurement, LightSensorError<I2C::Error>> {
+        if self.settings_changed {
+            let refresh_time = self.refresh_time_ms().await?;
+            debug!("waiting {} ms for a fresh sample", refresh_time);
+            self.dev.delay_ms(refresh_time).await;
+            self.settings_changed = false;
+        }
+
+        self.measure().await
+    }
+
+    /// Read both channels, together with the settings and quality flags.
+    pub async fn measure(&mut self) -> Result<Measurement, LightSensorError<I2C::Error>> {
+        let resolution = self.resolution().await?;
+        let (als_raw, white_raw) = self.read_channels(true).await?;
+
+        Ok(Measurement::new(&resolution, als_raw, white_raw, self.settings_changed))
+    }
+
+    /// Take `N` lux readings one [refresh time](Self::refresh_time_ms)
+    /// apart and combine them, e.g. to average out PWM-dimmed lighting.
+    ///
+    /// The first reading also waits if the settings just changed.
+    pub async fn read_aggregated<const N: usize>(&mut self, strategy: Strategy) -> Result<Aggregate, LightSensorError<I2C::Error>> {
+        const { assert!(N > 0, "at least one reading is needed") };
+
+        let refresh_time = self.refresh_time_ms().await?;
+        let resolution = self.resolution().await?;
+        let mut readings = [0.0f32; N];
+        for (index, reading) in readings.iter_mut().enumerate() {
+            if index > 0 || self.settings_changed {
+                self.dev.delay_ms(refresh_time).await;
+                self.settings_changed = false;
+            }
+            *reading = resolution.lux(self.dev.get_ambient_light_output().await?);
+        }
+
+        Ok(Aggregate::from_readings(&mut readings, strategy).expect("N is not zero"))
+    }
+
+    /// Power on, wait one integration period, read, and power off again.
+    ///
+    /// The sensor is shut down even if the read fails. Meant for nodes that
+    /// sleep between readings; the white channel is read only on request.
+    pub async fn measure_once(&mut self, read_white: bool) -> Result<Measurement, LightSensorError<I2C::Error>> {
+        self.dev.set_shutdown(i2c::Shutdown::PowerOn).await?;
+        let resolution = self.resolution().await?;
+        self.dev.delay_ms(resolution.integration_time().as_ms()).await;
+
+        let reading = self.read_channels(read_white).await;
+        let shutdown = self.dev.set_shutdown(i2c::Shutdown::PowerOff).await;
+        self.settings_changed = true;
+        let (als_raw, white_raw) = reading?;
+        shutdown?;
+
+        Ok(Measurement::new(&resolution, als_raw, white_raw, false))
+    }
+
+    /// Read and clear both threshold flags with a single access.
+    pub async fn read_interrupt_status(&mut self) -> Result<i2c::InterruptStatus, LightSensorError<I2C::Error>> {
+        Ok(self.dev.read_interrupt_status().await?)
+    }
+
+    /// Wait until `pin`, wired to the sensor's INT output, goes low, then
+    /// read the event behind it.
+    pub async fn wait_for_threshold_event<P: Wait>(&mut self, pin: &mut P) -> Result<ThresholdEvent, LightSensorError<I2C::Error>> {
+        pin.wait_for_low().await.map_err(|error| LightSensorError::Pin(digital::Error::kind(&error)))?;
+
+        self.read_threshold_event().await
+    }
+
+    /// Read the interrupt register once, which also clears it on the
+    /// device, together with the ambient light reading that caused it.
+    pub async fn read_threshold_event(&mut self) -> Result<ThresholdEvent, LightSensorError<I2C::Error>> {
+        let status = self.dev.read_interrupt_status().await?;
+        let raw = self.dev.get_ambient_light_output().await?;
+        let lux = self.convert_raw_to_lux(raw).await?;
+
+        Ok(ThresholdEvent {
+            high: status.high == i2c::Threshold::Exceeded,
+            low: status.low == i2c::Threshold::Exceeded,
+            raw,
+            lux,
+        })
+    }
+
+    /// Wait one [refresh time](Self::refresh_time_ms): a full integration
+    /// period plus the power-saving wait, after which a sample under the
+    /// current settings is ready.
+    pub async fn wait_for_integration(&mut self) -> Result<(), LightSensorError<I2C::Error>> {
+        let refresh_time = self.refresh_time_ms().await?;
+        self.dev.delay_ms(refresh_time).await;
+        self.settings_changed = false;
+
+        Ok(())
+    }
+
+    async fn read_channels(&mut self, read_white: bool) -> Result<(u16, Option<u16>), LightSensorError<I2C::Error>> {
+        let als_raw = self.dev.get_ambient_light_output().await?;
+        let white_raw = if read_white { Some(self.dev.get_white_light_output().await?) } else { None };
+
+        Ok((als_raw, white_raw))
+    }
+
+}
+
+
+/// Read the ID register at both standard addresses, see [`scan`](crate::scan).
+pub async fn scan<I2C: I2c>(i2c: &mut I2C) -> Result<ScanResult, LightSensorError<I2C::Error>> {
+    let mut found = [None; 2];
+    for (slot, address) in found.iter_mut().zip([Address::Default, Address::Alternative]) {
+        let mut buffer = [0u8; 2];
+        match i2c.write_read(address.into(), &[constants::registers::ID_REG], &mut buffer).await.map_err(i2c::LightSensorI2cError::from_bus) {
+            Ok(()) => *slot = Some((address, i2c::DeviceId::from_word(u16::from_le_bytes(buffer)))),
+            Err(i2c::LightSensorI2cError::NotPresent(_)) => (),
+            Err(error) => return Err(error.into()),
+        }
+    }
+
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    // Local imports
+    use super::*;
+    use i2c::constants;
+
+    // Public imports
+    use core::{future::Future, pin::pin, task::{Context, Poll, Waker}};
+    use embedded_hal_mock::eh1::{delay::NoopDelay, i2c::{Mock as I2cMock, Transaction as I2cTransaction}};
+    use embedded_hal_mock::eh1::digital::{Mock as PinMock, State as PinState, Transaction as PinTransaction};
+
+
+    /// Drive a future whose I/O never pends, as the mocks do.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut context = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+        }
+    }
+
+    #[test]
+    fn start_light_sensor() {
+        let address: u8 = constants::addresses::DEFAULT;
+        let expectations = [
+            I2cTransaction::write(address, vec![constants::registers::H_THRESH_REG, 0x00, 0x00]),
+            I2cTransaction::write(address, vec![constants::registers::L_THRESH_REG, 0x00, 0x00]),
+            I2cTransaction::write(address, vec![constants::registers::POWER_SAVE_REG, 0x00, 0x00]),
+            I2cTransaction::write(address, vec![constants::registers::SETTING_REG, 0x00, 0x1A]),
+        ];
+        let i2c = I2cMock::new(&expectations);
+        let mut i2c_clone = i2c.clone();
+
+        let result = block_on(LightSensor::build(i2c, Address::Default, NoopDelay::new()));
+        assert!(result.is_ok());
+
+        i2c_clone.done();
+    }
+
+    #[test]
+    fn ambient_light_lux() {
+        let address: u8 = constants::addresses::DEFAULT;
+        let expectations = [
+            I2cTransaction::write_read(address, vec![constants::registers::AMBIENT_LIGHT_DATA_REG], vec![0xE8, 0x03]),
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x08]),
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x08]),
+        ];
+        let i2c = I2cMock::new(&expectations);
+        let mut i2c_clone = i2c.clone();
+
+        let mut sensor = LightSensor::new(i2c, Address::Default, NoopDelay::new());
+        let lux = block_on(sensor.get_ambient_light_lux()).unwrap();
+        assert_eq!(lux, Resolution::new(i2c::Gain::X2, i2c::IntegrationTime::Ms100).lux(1000));
+
+        i2c_clone.done();
+    }
+
+    #[test]
+    fn threshold_event_on_interrupt_pin() {
+        let address: u8 = constants::addresses::DEFAULT;
+        let expectations = [
+            I2cTransaction::write_read(address, vec![constants::registers::INTERRUPT_REG], vec![0x00, 0x80]),
+            I2cTransaction::write_read(address, vec![constants::registers::AMBIENT_LIGHT_DATA_REG], vec![0x05, 0x00]),
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x1A]),
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x1A]),
+        ];
+        let i2c = I2cMock::new(&expectations);
+        let mut i2c_clone = i2c.clone();
+        let mut pin = PinMock::new(&[PinTransaction::wait_for_state(PinState::Low)]);
+
+        let mut sensor = LightSensor::new(i2c, Address::Default, NoopDelay::new());
+        let event = block_on(sensor.wait_for_threshold_event(&mut pin)).unwrap();
+        assert!(event.low && !event.high);
+        assert_eq!(event.raw, 5);
+
+        i2c_clone.done();
+        pin.done();
+    }
+
+}
@@ -0,0 +1,350 @@
+//! Per-enclosure calibration applied on top of the datasheet conversion.
+
+// Public imports
+use core::fmt::{Display, Formatter};
+
+// Local imports
+use crate::range;
+use crate::resolution::Resolution;
+use crate::variant::{ChipVariant, Veml7700};
+
+
+/// Correction from the sensor's linear reading to the lux in front of
+/// the enclosure.
+///
+/// Applied in order: the dark offset is subtracted, the non-linearity
+/// polynomial corrects readings above `correct_above`, and the result is
+/// divided by the transmittance `scale` of the cover glass or diffuser.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Calibration {
+    /// Coefficients `C0..=C3` of `C0·x + C1·x² + C2·x³ + C3·x⁴`, or `None` for a linear response.
+    pub polynomial: Option<[f64; 4]>,
+    /// Linear lux above which the polynomial applies.
+    pub correct_above: f64,
+    /// Fraction of the light that reaches the sensor, 1 without a cover.
+    pub scale: f64,
+    /// Lux read in the dark, removed from every reading.
+    pub dark_offset: f64,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Calibration::for_variant::<Veml7700>()
+    }
+}
+
+impl Calibration {
+    /// Datasheet behaviour of the part `V`: its own polynomial above 1000 lx,
+    /// no cover and no dark offset.
+    pub const fn for_variant<V: ChipVariant>() -> Self {
+        Calibration { polynomial: V::CORRECTION, correct_above: 1000.0, scale: 1.0, dark_offset: 0.0 }
+    }
+
+    /// Whether the polynomial applies to a linear reading of `lux`.
+    pub const fn corrects(&self, lux: f64) -> bool {
+        self.polynomial.is_some() && self.remove_dark_offset(lux) > self.correct_above
+    }
+
+    /// Calibrated lux for a linear reading of `lux`.
+    pub const fn apply(&self, lux: f64) -> f64 {
+        let mut lux = self.remove_dark_offset(lux);
+
+        if let Some([c0, c1, c2, c3]) = self.polynomial {
+            if lux > self.correct_above {
+                lux = lux * (c0 + lux * (c1 + lux * (c2 + lux * c3)));
+            }
+        }
+
+        lux / self.scale
+    }
+
+    /// Whether calibrated lux never falls between 0 and a linear `top`,
+    /// checked in as many steps as there are counts.
+    fn rises_up_to(&self, top: f64) -> bool {
+        let mut previous = self.apply(0.0);
+        for step in 1..=u16::MAX {
+            let lux = self.apply(top * f64::from(step) / f64::from(u16::MAX));
+            if lux < previous {
+                return false;
+            }
+            previous = lux;
+        }
+
+        true
+    }
+
+    const fn remove_dark_offset(&self, lux: f64) -> f64 {
+        let lux = lux - self.dark_offset;
+        if lux < 0.0 {
+            0.0
+        } else {
+            lux
+        }
+    }
+}
+
+
+/// Why a [`CalibrationFit`] could not produce a calibration.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FitError {
+    /// Every sample slot is taken.
+    Full,
+    /// Fewer samples than unknowns, or readings that do not pin them down.
+    Underdetermined,
+    /// The fitted curve falls somewhere below full scale, so lux could not
+    /// be converted back to counts.
+    Decreasing,
+}
+
+impl Display for FitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Full => write!(f, "no room for another sample"),
+            Self::Underdetermined => write!(f, "samples do not determine the fit"),
+            Self::Decreasing => write!(f, "fitted curve is not monotonic"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for FitError {}
+
+
+/// Fitted calibration and how far each sample lands from its reference.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FitReport<const N: usize> {
+    pub calibration: Calibration,
+    /// Reference minus calibrated reading, per sample in the order added.
+    residuals: [f64; N],
+    len: usize,
+}
+
+impl<const N: usize> FitReport<N> {
+    pub fn residuals(&self) -> &[f64] {
+        &self.residuals[..self.len]
+    }
+
+    /// Largest residual in magnitude.
+    pub fn max_residual(&self) -> f64 {
+        self.residuals().iter().fold(0.0, |max, residual| if residual.abs() > max { residual.abs() } else { max })
+    }
+
+    /// Mean of the squared residuals.
+    pub fn mean_square_residual(&self) -> f64 {
+        self.residuals().iter().map(|residual| residual * residual).sum::<f64>() / self.len as f64
+    }
+}
+
+
+/// Least-squares fit of a [`Calibration`] to readings taken next to a
+/// reference lux meter, for up to `N` light levels.
+///
+/// Readings are the sensor's linear lux, before any calibration.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CalibrationFit<const N: usize> {
+    samples: [(f64, f64); N],
+    len: usize,
+}
+
+impl<const N: usize> Default for CalibrationFit<N> {
+    fn default() -> Self {
+        CalibrationFit { samples: [(0.0, 0.0); N], len: 0 }
+    }
+}
+
+impl<const N: usize> CalibrationFit<N> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a linear lux reading and the reference lux at the same level.
+    pub fn add(&mut self, reading: f64, reference: f64) -> Result<(), FitError> {
+        let slot = self.samples.get_mut(self.len).ok_or(FitError::Full)?;
+        *slot = (reading, reference);
+        self.len += 1;
+
+        Ok(())
+    }
+
+    /// Record a raw count taken at `resolution` and the reference lux.
+    pub fn add_raw<V: ChipVariant>(&mut self, resolution: &Resolution<V>, raw: u16, reference: f64) -> Result<(), FitError> {
+        self.add(resolution.lux_per_count() * f64::from(raw), reference)
+    }
+
+    pub fn samples(&self) -> &[(f64, f64)] {
+        &self.samples[..self.len]
+    }
+
+    /// Keep the polynomial and dark offset of `base` and fit only the scale,
+    /// e.g. for the transmittance of a window.
+    pub fn fit_scale(&self, base: Calibration) -> Result<FitReport<N>, FitError> {
+        let unscaled = Calibration { scale: 1.0, ..base };
+        let (mut predicted_squares, mut cross) = (0.0, 0.0);
+        for &(reading, reference) in self.samples() {
+            let predicted = unscaled.apply(reading);
+            predicted_squares += predicted * predicted;
+            cross += predicted * reference;
+        }
+        if cross == 0.0 {
+            return Err(FitError::Underdetermined);
+        }
+
+        Ok(self.report(Calibration { scale: predicted_squares / cross, ..base }))
+    }
+
+    /// Fit all four polynomial coefficients, applied from 0 lx up, with no
+    /// dark offset and a scale of 1.
+    ///
+    /// The curve must rise from 0 lx up to the largest sample and the full
+    /// scale of the part `V`, as converting lux thresholds to counts relies on it.
+    pub fn fit_polynomial<V: ChipVariant>(&self) -> Result<FitReport<N>, FitError> {
+        if self.len < 4 {
+            return Err(FitError::Underdetermined);
+        }
+
+        // Readings are normalized to at most 1 to keep the normal equations
+        // well conditioned; the coefficients are scaled back afterwards.
+        let norm = self.samples().iter().fold(0.0, |max, (reading, _)| if reading.abs() > max { reading.abs() } else { max });
+        if norm == 0.0 {
+            return Err(FitError::Underdetermined);
+        }
+
+        let mut matrix = [[0.0f64; 5]; 4];
+        for &(reading, reference) in self.samples() {
+            let u = reading / norm;
+            let basis = [u, u * u, u * u * u, u * u * u * u];
+            for row in 0..4 {
+                for column in 0..4 {
+                    matrix[row][column] += basis[row] * basis[column];
+                }
+                matrix[row][4] += basis[row] * reference;
+            }
+        }
+        let solution = solve(matrix).ok_or(FitError::Underdetermined)?;
+
+        let mut polynomial = [0.0; 4];
+        let mut power = norm;
+        for (coefficient, normalized) in polynomial.iter_mut().zip(solution) {
+            *coefficient = normalized / power;
+            power *= norm;
+        }
+
+        let calibration = Calibration { polynomial: Some(polynomial), correct_above: 0.0, scale: 1.0, dark_offset: 0.0 };
+        let (gain, integration_time) = range::STEPS[0];
+        let full_scale = Resolution::<V>::for_variant(gain, integration_time).lux_per_count() * f64::from(u16::MAX);
+        if !calibration.rises_up_to(if norm > full_scale { norm } else { full_scale }) {
+            return Err(FitError::Decreasing);
+        }
+
+        Ok(self.report(calibration))
+    }
+
+    fn report(&self, calibration: Calibration) -> FitReport<N> {
+        let mut residuals = [0.0; N];
+        for (residual, &(reading, reference)) in residuals.iter_mut().zip(self.samples()) {
+            *residual = reference - calibration.apply(reading);
+        }
+
+        FitReport { calibration, residuals, len: self.len }
+    }
+}
+
+
+/// Solve a 4x4 system given as an augmented matrix, by Gaussian elimination
+/// with partial pivoting.
+fn solve(mut matrix: [[f64; 5]; 4]) -> Option<[f64; 4]> {
+    for column in 0..4 {
+        let pivot = (column..4).fold(column, |best, row| if matrix[row][column].abs() > matrix[best][column].abs() { row } else { best });
+        if matrix[pivot][column].abs() < 1e-12 {
+            return None;
+        }
+        matrix.swap(column, pivot);
+
+        let pivot_row = matrix[column];
+        for row in matrix.iter_mut().skip(column + 1) {
+            let factor = row[column] / pivot_row[column];
+            for (value, pivot_value) in row.iter_mut().zip(pivot_row).skip(column) {
+                *value -= factor * pivot_value;
+            }
+        }
+    }
+
+    let mut solution = [0.0; 4];
+    for row in (0..4).rev() {
+        let known: f64 = (row + 1..4).map(|k| matrix[row][k] * solution[k]).sum();
+        solution[row] = (matrix[row][4] - known) / matrix[row][row];
+    }
+    Some(solution)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variant::Veml6035;
+
+    #[test]
+    fn follows_published_polynomial() {
+        let calibration = Calibration::default();
+        let x: f64 = 2000.0;
+        let expected = 6.0135e-13 * x * x * x * x - 9.3924e-09 * x * x * x + 8.1488e-05 * x * x + 1.0023 * x;
+        assert!((calibration.apply(x) - expected).abs() < 1e-9);
+        assert_eq!(calibration.apply(500.0), 500.0);
+        assert_eq!(Calibration::for_variant::<Veml6035>().apply(2000.0), 2000.0);
+    }
+
+    #[test]
+    fn offset_then_scale() {
+        let calibration = Calibration { polynomial: None, correct_above: 1000.0, scale: 0.5, dark_offset: 2.0 };
+        assert_eq!(calibration.apply(12.0), 20.0);
+        assert_eq!(calibration.apply(1.0), 0.0);
+    }
+
+    #[test]
+    fn fits_window_transmittance() {
+        let mut fit = CalibrationFit::<4>::new();
+        for reading in [10.0, 100.0, 400.0] {
+            fit.add(reading, reading / 0.8).unwrap();
+        }
+
+        let report = fit.fit_scale(Calibration::for_variant::<Veml6035>()).unwrap();
+        assert!((report.calibration.scale - 0.8).abs() < 1e-12);
+        assert_eq!(report.residuals().len(), 3);
+        assert!(report.max_residual() < 1e-9);
+    }
+
+    #[test]
+    fn fits_polynomial() {
+        let truth = Calibration { correct_above: 0.0, ..Calibration::default() };
+        let mut fit = CalibrationFit::<8>::new();
+        for reading in [50.0, 500.0, 2000.0, 5000.0, 10000.0, 20000.0] {
+            fit.add(reading, truth.apply(reading)).unwrap();
+        }
+
+        let report = fit.fit_polynomial::<Veml7700>().unwrap();
+        assert!(report.max_residual() < 1e-6);
+        assert!(report.mean_square_residual() < 1e-12);
+        assert_eq!(CalibrationFit::<8>::new().fit_polynomial::<Veml7700>(), Err(FitError::Underdetermined));
+    }
+
+    #[test]
+    fn rejects_falling_polynomial() {
+        // Saturating readings pull the fit down above 2000 lx.
+        let mut fit = CalibrationFit::<8>::new();
+        for (reading, reference) in [(100.0, 100.0), (1000.0, 1000.0), (2000.0, 1800.0), (3000.0, 1900.0), (4000.0, 1500.0)] {
+            fit.add(reading, reference).unwrap();
+        }
+
+        assert_eq!(fit.fit_polynomial::<Veml7700>(), Err(FitError::Decreasing));
+    }
+
+    #[test]
+    fn rejects_extra_samples() {
+        let mut fit = CalibrationFit::<1>::new();
+        fit.add(1.0, 1.0).unwrap();
+        assert_eq!(fit.add(2.0, 2.0), Err(FitError::Full));
+    }
+}
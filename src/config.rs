@@ -0,0 +1,173 @@
+//! Whole-device configuration.
+
+// Local imports
+use crate::i2c::bitfield;
+use crate::variant::ChipVariant;
+use crate::i2c::{DecodeError, Gain, IntegrationTime, InterruptEnable, PersistenceProtectNumber, PowerSavingMode, PowerSavingModeEnable, Shutdown};
+
+
+/// Every host-writable setting of the sensor.
+///
+/// Applied with [`LightSensor::apply_config`](crate::LightSensor::apply_config),
+/// which writes each register once without reading it first.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Config {
+    pub gain: Gain,
+    pub integration_time: IntegrationTime,
+    pub persist_protect_number: PersistenceProtectNumber,
+    pub interrupt_enable: InterruptEnable,
+    pub shutdown: Shutdown,
+    pub power_saving_mode: PowerSavingMode,
+    pub power_saving_mode_enable: PowerSavingModeEnable,
+    /// Raw high threshold window.
+    pub high_threshold: u16,
+    /// Raw low threshold window.
+    pub low_threshold: u16,
+}
+
+impl Default for Config {
+    /// Powered on at 1/4 gain and 50 ms, everything else at its reset value.
+    fn default() -> Self {
+        Config {
+            gain: Gain::X1_4,
+            integration_time: IntegrationTime::Ms50,
+            persist_protect_number: PersistenceProtectNumber::N1,
+            interrupt_enable: InterruptEnable::Disable,
+            shutdown: Shutdown::PowerOn,
+            power_saving_mode: PowerSavingMode::M1,
+            power_saving_mode_enable: PowerSavingModeEnable::Disable,
+            high_threshold: 0,
+            low_threshold: 0,
+        }
+    }
+}
+
+impl Config {
+    /// Start a builder from the default configuration.
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::new()
+    }
+
+    /// Content of the configuration register of the part `V`.
+    pub fn setting_word<V: ChipVariant>(&self) -> u16 {
+        let mut state = 0;
+        state = V::encode_gain(state, self.gain);
+        state = bitfield::INTEGRATION_TIME.encode(state, self.integration_time);
+        state = bitfield::PERSIST_PROTECT_NUMBER.encode(state, self.persist_protect_number);
+        state = bitfield::INTERRUPT_ENABLE.encode(state, self.interrupt_enable);
+        bitfield::SHUTDOWN.encode(state, self.shutdown)
+    }
+
+    /// Content of the power-save register.
+    pub fn power_save_word(&self) -> u16 {
+        let state = bitfield::POWER_SAVING_MODE.encode(0, self.power_saving_mode);
+        bitfield::POWER_SAVING_MODE_ENABLE.encode(state, self.power_saving_mode_enable)
+    }
+
+    /// Rebuild a configuration from register contents of the part `V`.
+    pub fn from_words<V: ChipVariant>(setting: u16, power_save: u16, high_threshold: u16, low_threshold: u16) -> Result<Self, DecodeError> {
+        Ok(Config {
+            gain: V::decode_gain(setting)?,
+            integration_time: bitfield::INTEGRATION_TIME.decode(setting)?,
+            persist_protect_number: bitfield::PERSIST_PROTECT_NUMBER.decode(setting)?,
+            interrupt_enable: bitfield::INTERRUPT_ENABLE.decode(setting)?,
+            shutdown: bitfield::SHUTDOWN.decode(setting)?,
+            power_saving_mode: bitfield::POWER_SAVING_MODE.decode(power_save)?,
+            power_saving_mode_enable: bitfield::POWER_SAVING_MODE_ENABLE.decode(power_save)?,
+            high_threshold,
+            low_threshold,
+        })
+    }
+}
+
+
+/// Builder for [`Config`], starting from [`Config::default`].
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    pub fn new() -> Self {
+        ConfigBuilder::default()
+    }
+
+    pub fn gain(mut self, gain: Gain) -> Self {
+        self.config.gain = gain;
+        self
+    }
+
+    pub fn integration_time(mut self, integration_time: IntegrationTime) -> Self {
+        self.config.integration_time = integration_time;
+        self
+    }
+
+    pub fn persist_protect_number(mut self, persist_protect_number: PersistenceProtectNumber) -> Self {
+        self.config.persist_protect_number = persist_protect_number;
+        self
+    }
+
+    pub fn interrupt_enable(mut self, interrupt_enable: InterruptEnable) -> Self {
+        self.config.interrupt_enable = interrupt_enable;
+        self
+    }
+
+    pub fn shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.config.shutdown = shutdown;
+        self
+    }
+
+    pub fn power_saving_mode(mut self, power_saving_mode: PowerSavingMode) -> Self {
+        self.config.power_saving_mode = power_saving_mode;
+        self
+    }
+
+    pub fn power_saving_mode_enable(mut self, power_saving_mode_enable: PowerSavingModeEnable) -> Self {
+        self.config.power_saving_mode_enable = power_saving_mode_enable;
+        self
+    }
+
+    pub fn high_threshold(mut self, high_threshold: u16) -> Self {
+        self.config.high_threshold = high_threshold;
+        self
+    }
+
+    pub fn low_threshold(mut self, low_threshold: u16) -> Self {
+        self.config.low_threshold = low_threshold;
+        self
+    }
+
+    pub fn build(self) -> Config {
+        self.config
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variant::{Veml6035, Veml7700};
+
+    #[test]
+    fn words_round_trip() {
+        let config = Config::builder()
+            .gain(Gain::X2)
+            .integration_time(IntegrationTime::Ms800)
+            .persist_protect_number(PersistenceProtectNumber::N4)
+            .interrupt_enable(InterruptEnable::Enable)
+            .shutdown(Shutdown::PowerOff)
+            .power_saving_mode(PowerSavingMode::M3)
+            .power_saving_mode_enable(PowerSavingModeEnable::Enable)
+            .high_threshold(4000)
+            .low_threshold(200)
+            .build();
+
+        assert_eq!(config.setting_word::<Veml7700>(), 0x08E3);
+        assert_eq!(config.setting_word::<Veml6035>(), 0x04E3);
+        assert_eq!(config.power_save_word(), 0x0005);
+        assert_eq!(Config::from_words::<Veml7700>(0x08E3, 0x0005, 4000, 200), Ok(config));
+        assert_eq!(Config::from_words::<Veml6035>(0x04E3, 0x0005, 4000, 200), Ok(config));
+    }
+}
@@ -0,0 +1,256 @@
+//! Allocation-free smoothing of a stream of lux readings.
+//!
+//! Readings passed with the [`Resolution`] they were taken at restart the
+//! filter whenever gain or integration time change, e.g. after
+//! [`get_ambient_light_lux_auto`](crate::LightSensor::get_ambient_light_lux_auto)
+//! moved to another step, whose resolution
+//! [`LightSensor::resolution`](crate::LightSensor::resolution) then returns.
+
+// Local imports
+use crate::aggregate::{Aggregate, Strategy};
+use crate::i2c::{Gain, IntegrationTime};
+use crate::resolution::Resolution;
+use crate::variant::ChipVariant;
+
+
+/// Gain and integration time of the last reading, to notice a new scale.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Scale(Option<(Gain, IntegrationTime)>);
+
+impl Scale {
+    /// Record the scale of a reading; true if it differs from the last one.
+    fn changed<V: ChipVariant>(&mut self, resolution: &Resolution<V>) -> bool {
+        let scale = Some((resolution.gain(), resolution.integration_time()));
+        let changed = self.0.is_some() && self.0 != scale;
+        self.0 = scale;
+        changed
+    }
+}
+
+
+/// Exponential moving average.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ema {
+    /// Weight of a new reading, between 0 and 1.
+    alpha: f32,
+    value: Option<f32>,
+    scale: Scale,
+}
+
+impl Ema {
+    pub fn new(alpha: f32) -> Self {
+        Ema { alpha, value: None, scale: Scale::default() }
+    }
+
+    pub fn update(&mut self, lux: f32) -> f32 {
+        let value = match self.value {
+            Some(value) => value + self.alpha * (lux - value),
+            None => lux,
+        };
+        self.value = Some(value);
+        value
+    }
+
+    /// Update with a reading taken at `resolution`, restarting on a new scale.
+    pub fn update_at<V: ChipVariant>(&mut self, lux: f32, resolution: &Resolution<V>) -> f32 {
+        if self.scale.changed(resolution) {
+            self.value = None;
+        }
+        self.update(lux)
+    }
+
+    pub fn value(&self) -> Option<f32> {
+        self.value
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new(self.alpha);
+    }
+}
+
+
+/// The last `N` readings, averaged or their median taken.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MovingWindow<const N: usize> {
+    readings: [f32; N],
+    /// Slot the next reading goes into.
+    next: usize,
+    len: usize,
+    scale: Scale,
+}
+
+impl<const N: usize> Default for MovingWindow<N> {
+    fn default() -> Self {
+        MovingWindow { readings: [0.0; N], next: 0, len: 0, scale: Scale::default() }
+    }
+}
+
+impl<const N: usize> MovingWindow<N> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a reading, dropping the oldest once the window is full.
+    pub fn push(&mut self, lux: f32) {
+        if N == 0 {
+            return;
+        }
+        self.readings[self.next] = lux;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+    }
+
+    /// Add a reading taken at `resolution`, emptying the window on a new scale.
+    pub fn push_at<V: ChipVariant>(&mut self, lux: f32, resolution: &Resolution<V>) {
+        if self.scale.changed(resolution) {
+            self.next = 0;
+            self.len = 0;
+        }
+        self.push(lux);
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn mean(&self) -> Option<f32> {
+        self.aggregate(Strategy::Mean).map(|aggregate| aggregate.value)
+    }
+
+    pub fn median(&self) -> Option<f32> {
+        self.aggregate(Strategy::Median).map(|aggregate| aggregate.value)
+    }
+
+    /// Combine the readings in the window.
+    pub fn aggregate(&self, strategy: Strategy) -> Option<Aggregate> {
+        let mut readings = self.readings;
+        Aggregate::from_readings(&mut readings[..self.len], strategy)
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+
+/// One-dimensional Kalman filter for a slowly changing light level.
+///
+/// The measurement noise follows the resolution of each reading, so finer
+/// settings are trusted more.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Kalman {
+    /// Variance the light level gains between two readings, in lx².
+    process_noise: f32,
+    /// Standard deviation of a reading, in counts.
+    noise_counts: f32,
+    /// Estimate and its variance.
+    state: Option<(f32, f32)>,
+    scale: Scale,
+}
+
+impl Kalman {
+    pub fn new(process_noise: f32, noise_counts: f32) -> Self {
+        Kalman { process_noise, noise_counts, state: None, scale: Scale::default() }
+    }
+
+    /// Fold in a reading taken at `resolution`, restarting on a new scale.
+    pub fn update<V: ChipVariant>(&mut self, lux: f32, resolution: &Resolution<V>) -> f32 {
+        if self.scale.changed(resolution) {
+            self.state = None;
+        }
+
+        let deviation = self.noise_counts * resolution.lux_per_count() as f32;
+        let measurement_noise = deviation * deviation;
+        let (estimate, variance) = match self.state {
+            Some((estimate, variance)) => {
+                let variance = variance + self.process_noise;
+                let gain = variance / (variance + measurement_noise);
+                (estimate + gain * (lux - estimate), (1.0 - gain) * variance)
+            },
+            None => (lux, measurement_noise),
+        };
+        self.state = Some((estimate, variance));
+        estimate
+    }
+
+    pub fn value(&self) -> Option<f32> {
+        self.state.map(|(estimate, _)| estimate)
+    }
+
+    /// Variance of the estimate, in lx².
+    pub fn variance(&self) -> Option<f32> {
+        self.state.map(|(_, variance)| variance)
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new(self.process_noise, self.noise_counts);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ema_restarts_on_new_scale() {
+        let fine = Resolution::new(Gain::X2, IntegrationTime::Ms100);
+        let coarse = Resolution::new(Gain::X1_8, IntegrationTime::Ms100);
+
+        let mut ema = Ema::new(0.5);
+        assert_eq!(ema.update_at(10.0, &fine), 10.0);
+        assert_eq!(ema.update_at(20.0, &fine), 15.0);
+        assert_eq!(ema.update_at(100.0, &coarse), 100.0);
+        ema.reset();
+        assert_eq!(ema.value(), None);
+    }
+
+    #[test]
+    fn window_keeps_last_readings() {
+        let mut window = MovingWindow::<3>::new();
+        assert_eq!(window.mean(), None);
+        for lux in [1.0, 2.0, 90.0, 4.0] {
+            window.push(lux);
+        }
+        assert_eq!(window.len(), 3);
+        assert_eq!(window.median(), Some(4.0));
+        assert_eq!(window.mean(), Some(32.0));
+
+        window.push_at(7.0, &Resolution::new(Gain::X2, IntegrationTime::Ms100));
+        window.push_at(8.0, &Resolution::new(Gain::X1, IntegrationTime::Ms100));
+        assert_eq!(window.len(), 1);
+        assert_eq!(window.mean(), Some(8.0));
+    }
+
+    #[test]
+    fn kalman_trusts_finer_resolution() {
+        let fine = Resolution::new(Gain::X2, IntegrationTime::Ms800);
+        let coarse = Resolution::new(Gain::X1_8, IntegrationTime::Ms25);
+
+        let mut kalman = Kalman::new(0.0, 1.0);
+        assert_eq!(kalman.update(100.0, &fine), 100.0);
+        let estimate = kalman.update(110.0, &fine);
+        assert!(estimate > 104.9 && estimate < 105.1);
+
+        let mut kalman = Kalman::new(1.0, 1.0);
+        kalman.update(100.0, &coarse);
+        let coarse_step = kalman.update(110.0, &coarse) - 100.0;
+        let mut kalman = Kalman::new(1.0, 1.0);
+        kalman.update(100.0, &fine);
+        let fine_step = kalman.update(110.0, &fine) - 100.0;
+        assert!(fine_step > coarse_step);
+
+        assert_eq!(kalman.update(50.0, &coarse), 50.0);
+    }
+}
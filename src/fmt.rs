@@ -0,0 +1,11 @@
+// Diagnostics forwarded to `log` or `defmt` when either feature is enabled,
+// and compiled out otherwise.
+
+macro_rules! debug {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "log")]
+        log::debug!($($arg)*);
+        #[cfg(feature = "defmt")]
+        defmt::debug!($($arg)*);
+    }};
+}
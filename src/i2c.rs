@@ -1,22 +1,94 @@
 // Local modules
 pub mod constants;
+pub(crate) mod bitfield;
+#[cfg(feature = "async")]
+pub mod asynch;
 
 // Public imports
-use embedded_hal::{i2c::I2c, delay::DelayNs};
-
-use byteorder::{ByteOrder, LittleEndian};
+use core::fmt::{Debug, Display, Formatter};
+use core::marker::PhantomData;
+use embedded_hal::{i2c::{I2c, Error, ErrorKind, NoAcknowledgeSource}, delay::DelayNs};
 
 // Local imports
+use crate::variant::{ChipVariant, Veml7700};
+
+
+/// Time the sensor needs after power-on before it can be used.
+pub(crate) const POWER_ON_DELAY_MS: u32 = 4;
+
+/// Time between two samples in milliseconds: one integration period, plus
+/// the power-saving wait when power saving is enabled.
+pub const fn refresh_time_ms(integration_time: IntegrationTime, mode: PowerSavingMode, enable: PowerSavingModeEnable) -> u32 {
+    match enable {
+        PowerSavingModeEnable::Enable => integration_time.as_ms() + mode.wait_ms(),
+        PowerSavingModeEnable::Disable => integration_time.as_ms(),
+    }
+}
+
+/// Errors returned by [`LightSensorI2c`], generic over the bus error `E`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LightSensorI2cError<E> {
+    /// The bus transaction failed.
+    Bus(E),
+    /// No device acknowledged the address.
+    NotPresent(E),
+    /// A register held a value that does not map to a known setting.
+    InvalidValue { register: u8, value: u16 },
+}
+
+impl<E: Debug> Display for LightSensorI2cError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Bus(error) => write!(f, "bus error: {:?}", error),
+            Self::NotPresent(error) => write!(f, "device not present: {:?}", error),
+            Self::InvalidValue { register, value } => write!(f, "invalid value {:#06x} in register {:#04x}", value, register),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: Debug> std::error::Error for LightSensorI2cError<E> {}
+
+impl<E> From<DecodeError> for LightSensorI2cError<E> {
+    fn from(error: DecodeError) -> Self {
+        Self::InvalidValue { register: error.register, value: error.value }
+    }
+}
+
+impl<E: Error> LightSensorI2cError<E> {
+    /// Wrap a bus error, telling an address NACK apart from other failures.
+    pub fn from_bus(error: E) -> Self {
+        match error.kind() {
+            ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address) => Self::NotPresent(error),
+            _ => Self::Bus(error),
+        }
+    }
+
+    /// Kind of the underlying bus error, if the error came from the bus.
+    pub fn kind(&self) -> Option<ErrorKind> {
+        match self {
+            Self::Bus(error) | Self::NotPresent(error) => Some(error.kind()),
+            Self::InvalidValue { .. } => None,
+        }
+    }
+}
 
 
-#[derive(Debug)]
-pub enum LightSensorI2cError {
-    ConversionError,
-    IOError
+/// A register field held a value that does not map to a known setting.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DecodeError {
+    /// Register the value was read from.
+    pub register: u8,
+    /// Raw value that failed to decode.
+    pub value: u16,
 }
 
 
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Gain {
     X1 = 0b00,  // 1x gain
     X2 = 0b01,  // 2x gain
@@ -24,14 +96,16 @@ pub enum Gain {
     X1_4 = 0b11  // 1/4x gain
 }
 
-impl From<u16> for Gain {
-    fn from(item: u16) -> Self {
+impl TryFrom<u16> for Gain {
+    type Error = DecodeError;
+
+    fn try_from(item: u16) -> Result<Self, Self::Error> {
         match item {
-            0 => Self::X1,
-            1 => Self::X2,
-            2 => Self::X1_8,
-            3 => Self::X1_4,
-            _ => panic!("Not expected")
+            0 => Ok(Self::X1),
+            1 => Ok(Self::X2),
+            2 => Ok(Self::X1_8),
+            3 => Ok(Self::X1_4),
+            _ => Err(DecodeError { register: constants::registers::SETTING_REG, value: item })
         }
     }
 }
@@ -48,6 +122,8 @@ impl From<Gain> for u16 {
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum IntegrationTime {
     Ms25 = 0b1100,
     Ms50 = 0b1000,
@@ -57,16 +133,32 @@ pub enum IntegrationTime {
     Ms800 = 0b0011
 }
 
-impl From<u16> for IntegrationTime {
-    fn from(item: u16) -> Self {
+impl TryFrom<u16> for IntegrationTime {
+    type Error = DecodeError;
+
+    fn try_from(item: u16) -> Result<Self, Self::Error> {
         match item {
-            0b1100 => Self::Ms25,
-            0b1000 => Self::Ms50,
-            0b0000 => Self::Ms100,
-            0b0001 => Self::Ms200,
-            0b0010 => Self::Ms400,
-            0b0011 => Self::Ms800,
-            _ => panic!("Not expected")
+            0b1100 => Ok(Self::Ms25),
+            0b1000 => Ok(Self::Ms50),
+            0b0000 => Ok(Self::Ms100),
+            0b0001 => Ok(Self::Ms200),
+            0b0010 => Ok(Self::Ms400),
+            0b0011 => Ok(Self::Ms800),
+            _ => Err(DecodeError { register: constants::registers::SETTING_REG, value: item })
+        }
+    }
+}
+
+impl IntegrationTime {
+    /// Integration time in milliseconds.
+    pub const fn as_ms(self) -> u32 {
+        match self {
+            Self::Ms25 => 25,
+            Self::Ms50 => 50,
+            Self::Ms100 => 100,
+            Self::Ms200 => 200,
+            Self::Ms400 => 400,
+            Self::Ms800 => 800,
         }
     }
 }
@@ -85,6 +177,8 @@ impl From<IntegrationTime> for u16 {
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PersistenceProtectNumber {
     N1 = 0b00,
     N2 = 0b01,
@@ -92,14 +186,16 @@ pub enum PersistenceProtectNumber {
     N8 = 0b11
 }
 
-impl From<u16> for PersistenceProtectNumber {
-    fn from(item: u16) -> Self {
+impl TryFrom<u16> for PersistenceProtectNumber {
+    type Error = DecodeError;
+
+    fn try_from(item: u16) -> Result<Self, Self::Error> {
         match item {
-            0b00 => Self::N1,
-            0b01 => Self::N2,
-            0b10 => Self::N4,
-            0b11 => Self::N8,
-            _ => panic!("Not expected")
+            0b00 => Ok(Self::N1),
+            0b01 => Ok(Self::N2),
+            0b10 => Ok(Self::N4),
+            0b11 => Ok(Self::N8),
+            _ => Err(DecodeError { register: constants::registers::SETTING_REG, value: item })
         }
     }
 }
@@ -116,6 +212,8 @@ impl From<PersistenceProtectNumber> for u16 {
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PowerSavingMode {
     M1 = 0b00,  // Fastest, most current
     M2 = 0b01,
@@ -123,14 +221,28 @@ pub enum PowerSavingMode {
     M4 = 0b11  // Slowest, least current
 }
 
-impl From<u16> for PowerSavingMode {
-    fn from(item: u16) -> Self {
+impl TryFrom<u16> for PowerSavingMode {
+    type Error = DecodeError;
+
+    fn try_from(item: u16) -> Result<Self, Self::Error> {
         match item {
-            0 => Self::M1,
-            1 => Self::M2,
-            2 => Self::M3,
-            3 => Self::M4,
-            _ => panic!("Not expected")
+            0 => Ok(Self::M1),
+            1 => Ok(Self::M2),
+            2 => Ok(Self::M3),
+            3 => Ok(Self::M4),
+            _ => Err(DecodeError { register: constants::registers::POWER_SAVE_REG, value: item })
+        }
+    }
+}
+
+impl PowerSavingMode {
+    /// Wait between two measurements in milliseconds.
+    pub const fn wait_ms(self) -> u32 {
+        match self {
+            Self::M1 => 500,
+            Self::M2 => 1000,
+            Self::M3 => 2000,
+            Self::M4 => 4000,
         }
     }
 }
@@ -147,17 +259,21 @@ impl From<PowerSavingMode> for u16 {
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PowerSavingModeEnable {
     Disable = 0b0,
     Enable = 0b1
 }
 
-impl From<u16> for PowerSavingModeEnable {
-    fn from(item: u16) -> Self {
+impl TryFrom<u16> for PowerSavingModeEnable {
+    type Error = DecodeError;
+
+    fn try_from(item: u16) -> Result<Self, Self::Error> {
         match item {
-            0 => Self::Disable,
-            1 => Self::Enable,
-            _ => panic!("Not expected")
+            0 => Ok(Self::Disable),
+            1 => Ok(Self::Enable),
+            _ => Err(DecodeError { register: constants::registers::POWER_SAVE_REG, value: item })
         }
     }
 }
@@ -173,17 +289,21 @@ impl From<PowerSavingModeEnable> for u16 {
 
 
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum InterruptEnable {
     Disable = 0b0,
     Enable = 0b1,
 }
 
-impl From<u16> for InterruptEnable {
-    fn from(item: u16) -> Self {
+impl TryFrom<u16> for InterruptEnable {
+    type Error = DecodeError;
+
+    fn try_from(item: u16) -> Result<Self, Self::Error> {
         match item {
-            0 => Self::Disable,
-            1 => Self::Enable,
-            _ => panic!("Not expected")
+            0 => Ok(Self::Disable),
+            1 => Ok(Self::Enable),
+            _ => Err(DecodeError { register: constants::registers::SETTING_REG, value: item })
         }
     }
 }
@@ -198,17 +318,21 @@ impl From<InterruptEnable> for u16 {
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Shutdown {
     PowerOn,
     PowerOff
 }
 
-impl From<u16> for Shutdown {
-    fn from(item: u16) -> Self {
+impl TryFrom<u16> for Shutdown {
+    type Error = DecodeError;
+
+    fn try_from(item: u16) -> Result<Self, Self::Error> {
         match item {
-            0 => Self::PowerOn,
-            1 => Self::PowerOff,
-            _ => panic!("Not expected")
+            0 => Ok(Self::PowerOn),
+            1 => Ok(Self::PowerOff),
+            _ => Err(DecodeError { register: constants::registers::SETTING_REG, value: item })
         }
     }
 }
@@ -223,17 +347,21 @@ impl From<Shutdown> for u16 {
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Threshold {
     NotExceeded = 0,
     Exceeded = 1,
 }
 
-impl From<u16> for Threshold {
-    fn from(item: u16) -> Self {
+impl TryFrom<u16> for Threshold {
+    type Error = DecodeError;
+
+    fn try_from(item: u16) -> Result<Self, Self::Error> {
         match item {
-            0 => Self::NotExceeded,
-            1 => Self::Exceeded,
-            _ => panic!("Not expected")
+            0 => Ok(Self::NotExceeded),
+            1 => Ok(Self::Exceeded),
+            _ => Err(DecodeError { register: constants::registers::INTERRUPT_REG, value: item })
         }
     }
 }
@@ -247,10 +375,56 @@ impl From<Threshold> for u16 {
     }
 }
 
+/// Both threshold flags of the interrupt register, taken from one read.
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InterruptStatus {
+    pub low: Threshold,
+    pub high: Threshold,
+}
+
+impl InterruptStatus {
+    /// Decode the content of the interrupt register.
+    pub fn from_word(state: u16) -> Result<Self, DecodeError> {
+        Ok(InterruptStatus {
+            low: bitfield::LOW_THRESHOLD_EXCEEDED.decode(state)?,
+            high: bitfield::HIGH_THRESHOLD_EXCEEDED.decode(state)?,
+        })
+    }
+}
+
+/// Content of the ID register.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceId {
+    /// Part code, see [`ChipVariant::DEVICE_ID`].
+    pub device: u8,
+    /// Slave address option code: 0xC4 for 0x10, the only address of the
+    /// VEML7700, or 0xD4 for the VEML6030 at 0x48.
+    pub address_option: u8,
+}
+
+impl DeviceId {
+    /// Decode the content of the ID register.
+    pub fn from_word(state: u16) -> Self {
+        let [device, address_option] = state.to_le_bytes();
+        DeviceId { device, address_option }
+    }
+}
+
+/// Bus address of the sensor.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "u8", into = "u8"))]
 pub enum Address {
     Default,
     Alternative,
+    /// Any other 7-bit address, e.g. behind an address translator.
+    /// Build it with `Address::try_from` to have the range checked.
+    Custom(u8),
 }
 
 impl From<Address> for u8 {
@@ -258,250 +432,286 @@ impl From<Address> for u8 {
         match item {
             Address::Default => constants::addresses::DEFAULT,
             Address::Alternative => constants::addresses::ALTERNATIVE,
+            Address::Custom(address) => address,
+        }
+    }
+}
+
+impl TryFrom<u8> for Address {
+    type Error = AddressError;
+
+    fn try_from(item: u8) -> Result<Self, Self::Error> {
+        match item {
+            constants::addresses::DEFAULT => Ok(Address::Default),
+            constants::addresses::ALTERNATIVE => Ok(Address::Alternative),
+            0..=0x7F => Ok(Address::Custom(item)),
+            _ => Err(AddressError { address: item }),
         }
     }
 }
 
+/// An address does not fit in 7 bits.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AddressError {
+    pub address: u8,
+}
+
+impl Display for AddressError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "address {:#04x} is not a 7-bit address", self.address)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AddressError {}
+
+
+/// Bus and delay given back by a driver, or the driver itself with the
+/// error that kept it from shutting the sensor down.
+pub type Destroyed<I2C, Delay, Driver, E> = Result<(I2C, Delay), (Driver, E)>;
+
 
 /// I2C wrapper for LightSensor
-pub struct LightSensorI2c<I2C, Delay>{
+pub struct LightSensorI2c<I2C, Delay, V = Veml7700>{
     i2c: I2C,
     address: u8,
-    delay: Delay
+    delay: Delay,
+    shadow: bitfield::Shadow,
+    variant: PhantomData<V>,
 }
 
 
 impl<I2C: I2c, Delay: DelayNs> LightSensorI2c<I2C, Delay>{
     /// Create new LightSensorI2c.
     pub fn new(i2c: I2C, address: u8, delay: Delay) -> LightSensorI2c<I2C, Delay> {
-        LightSensorI2c { i2c, address, delay}
+        Self::with_variant(i2c, address, delay, Veml7700)
     }
+}
 
-    pub fn get_gain(&mut self) -> Result<Gain, LightSensorI2cError> {
-        let state = read_and_convert_to_u16(self, constants::registers::SETTING_REG).unwrap();
-        let gain = clip_u16(state, 11, 2);
 
-        Ok(gain.into())
+impl<I2C: I2c, Delay: DelayNs, V: ChipVariant> LightSensorI2c<I2C, Delay, V>{
+    /// Create new LightSensorI2c for the part `V`.
+    pub fn with_variant(i2c: I2C, address: u8, delay: Delay, _variant: V) -> LightSensorI2c<I2C, Delay, V> {
+        LightSensorI2c { i2c, address, delay, shadow: bitfield::Shadow::default(), variant: PhantomData }
     }
 
-    pub fn set_gain(&mut self, gain: Gain) -> Result<(), LightSensorI2cError> {
-        let old_state = read_and_convert_to_u16(self, constants::registers::SETTING_REG).unwrap();
-        let new_state = insert_u16(old_state, 11, 2, gain.into());
-        convert_and_write_u16(self, constants::registers::SETTING_REG, new_state).unwrap();
-
-        Ok(())
+    /// Address the driver talks to.
+    pub fn address(&self) -> u8 {
+        self.address
     }
 
-    pub fn get_integration_time(&mut self) -> Result<IntegrationTime, LightSensorI2cError> {
-        let state = read_and_convert_to_u16(self, constants::registers::SETTING_REG).unwrap();
-        let integration_time = clip_u16(state, 6, 4);
+    /// Give back the bus and the delay, leaving the sensor as it is.
+    pub fn destroy(self) -> (I2C, Delay) {
+        (self.i2c, self.delay)
+    }
 
-        Ok(integration_time.into())
+    /// Shut the sensor down, then give back the bus and the delay.
+    ///
+    /// On a bus error the driver is handed back along with it.
+    pub fn destroy_powered_off(mut self) -> Destroyed<I2C, Delay, Self, LightSensorI2cError<I2C::Error>> {
+        match self.set_shutdown(Shutdown::PowerOff) {
+            Ok(()) => Ok(self.destroy()),
+            Err(error) => Err((self, error)),
+        }
     }
 
-    pub fn set_integration_time(&mut self, integration_time: IntegrationTime) -> Result<(), LightSensorI2cError> {
-        let old_state = read_and_convert_to_u16(self, constants::registers::SETTING_REG).unwrap();
-        let new_state = insert_u16(old_state, 6, 4, integration_time.into());
-        convert_and_write_u16(self, constants::registers::SETTING_REG, new_state).unwrap();
+    /// Read the configuration and power-save registers into the driver's
+    /// shadow copy. Until [`invalidate`](Self::invalidate) is called, field
+    /// reads are served from the copy and field writes skip the read.
+    pub fn sync_from_device(&mut self) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        for register in [constants::registers::SETTING_REG, constants::registers::POWER_SAVE_REG] {
+            let state = read_and_convert_to_u16(self, register)?;
+            self.shadow.set(register, state);
+        }
 
         Ok(())
     }
 
-    pub fn get_persist_protect_number(&mut self) -> Result<PersistenceProtectNumber, LightSensorI2cError> {
-        let state = read_and_convert_to_u16(self, constants::registers::SETTING_REG).unwrap();
-        let ppn = clip_u16(state, 4, 2);
-        
-        Ok(ppn.into())
+    /// Drop the shadow copy, so every access goes to the bus again.
+    pub fn invalidate(&mut self) {
+        self.shadow.clear();
     }
 
-    pub fn set_persist_protect_number(&mut self, persistence_protect_number: PersistenceProtectNumber) -> Result<(), LightSensorI2cError> {
-        let old_state = read_and_convert_to_u16(self, constants::registers::SETTING_REG).unwrap();
-        let new_state = insert_u16(old_state, 4, 2, persistence_protect_number.into());
-        convert_and_write_u16(self, constants::registers::SETTING_REG, new_state).unwrap();
+    pub fn get_gain(&mut self) -> Result<Gain, LightSensorI2cError<I2C::Error>> {
+        let state = self.read_word(constants::registers::SETTING_REG)?;
 
-        Ok(())
+        Ok(V::decode_gain(state)?)
     }
 
-    pub fn get_interrupt_enabled(&mut self) -> Result<InterruptEnable, LightSensorI2cError> {
-        let state = read_and_convert_to_u16(self, constants::registers::SETTING_REG).unwrap();
-        let interrupt = clip_u16(state, 1, 1);
+    pub fn set_gain(&mut self, gain: Gain) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        let old_state = self.read_word(constants::registers::SETTING_REG)?;
+        let new_state = V::encode_gain(old_state, gain);
+        self.write_word(constants::registers::SETTING_REG, new_state)
+    }
 
-        Ok(interrupt.into())
+    pub fn get_integration_time(&mut self) -> Result<IntegrationTime, LightSensorI2cError<I2C::Error>> {
+        read_field(self, bitfield::INTEGRATION_TIME)
     }
 
-    pub fn set_interrupt_enabled(&mut self, interrupt_enable: InterruptEnable) -> Result<(), LightSensorI2cError> {
-        let old_state = read_and_convert_to_u16(self, constants::registers::SETTING_REG).unwrap();
-        let new_sate = insert_u16(old_state, 1, 1, interrupt_enable.into());
-        convert_and_write_u16(self, constants::registers::SETTING_REG, new_sate).unwrap();
-        
-        Ok(())
+    pub fn set_integration_time(&mut self, integration_time: IntegrationTime) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        write_field(self, bitfield::INTEGRATION_TIME, integration_time)
     }
 
-    pub fn get_shutdown(&mut self) -> Result<Shutdown, LightSensorI2cError> {
-        let state = read_and_convert_to_u16(self, constants::registers::SETTING_REG).unwrap();
-        let shut_down = clip_u16(state, 0, 1);
+    /// Set gain and integration time with a single register write.
+    pub fn set_scale(&mut self, gain: Gain, integration_time: IntegrationTime) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        let old_state = self.read_word(constants::registers::SETTING_REG)?;
+        let new_state = bitfield::INTEGRATION_TIME.encode(V::encode_gain(old_state, gain), integration_time);
+        self.write_word(constants::registers::SETTING_REG, new_state)
+    }
 
-        Ok(shut_down.into())
+    pub fn get_persist_protect_number(&mut self) -> Result<PersistenceProtectNumber, LightSensorI2cError<I2C::Error>> {
+        read_field(self, bitfield::PERSIST_PROTECT_NUMBER)
     }
 
-    pub fn set_shutdown(&mut self, shutdown: Shutdown) -> Result<(), LightSensorI2cError> {
-        let old_state = read_and_convert_to_u16(self, constants::registers::SETTING_REG).unwrap();
-        let new_sate = insert_u16(old_state, 0, 1, shutdown.clone().into());
-        convert_and_write_u16(self, constants::registers::SETTING_REG, new_sate).unwrap();
-        
-        match shutdown {
-            Shutdown::PowerOn => self.delay.delay_ms(4),
-            _ => (),
-        }
+    pub fn set_persist_protect_number(&mut self, persistence_protect_number: PersistenceProtectNumber) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        write_field(self, bitfield::PERSIST_PROTECT_NUMBER, persistence_protect_number)
+    }
 
-        Ok(())
+    pub fn get_interrupt_enabled(&mut self) -> Result<InterruptEnable, LightSensorI2cError<I2C::Error>> {
+        read_field(self, bitfield::INTERRUPT_ENABLE)
     }
 
-    pub fn get_high_threshold_window(&mut self) -> Result<u16, LightSensorI2cError> {
-        let high_threhold_window = read_and_convert_to_u16(self, constants::registers::H_THRESH_REG).unwrap();
+    pub fn set_interrupt_enabled(&mut self, interrupt_enable: InterruptEnable) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        write_field(self, bitfield::INTERRUPT_ENABLE, interrupt_enable)
+    }
 
-        Ok(high_threhold_window)
+    pub fn get_shutdown(&mut self) -> Result<Shutdown, LightSensorI2cError<I2C::Error>> {
+        read_field(self, bitfield::SHUTDOWN)
     }
 
-    pub fn set_high_threshold_window(&mut self, threshold: u16) -> Result<(), LightSensorI2cError> {
-        convert_and_write_u16(self, constants::registers::H_THRESH_REG, threshold).unwrap();
+    pub fn set_shutdown(&mut self, shutdown: Shutdown) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        write_field(self, bitfield::SHUTDOWN, shutdown)?;
+
+        if shutdown == Shutdown::PowerOn {
+            self.delay.delay_ms(POWER_ON_DELAY_MS);
+        }
 
         Ok(())
     }
 
-    pub fn get_low_threshold_window(&mut self) -> Result<u16, LightSensorI2cError> {
-        let low_threshold_window = read_and_convert_to_u16(self, constants::registers::L_THRESH_REG).unwrap();
-
-        Ok(low_threshold_window)
+    pub fn get_high_threshold_window(&mut self) -> Result<u16, LightSensorI2cError<I2C::Error>> {
+        read_and_convert_to_u16(self, constants::registers::H_THRESH_REG)
     }
 
-    pub fn set_low_threshold_window(&mut self, threshold: u16) -> Result<(), LightSensorI2cError> {
-        convert_and_write_u16(self, constants::registers::L_THRESH_REG, threshold).unwrap();
-
-        Ok(())
+    pub fn set_high_threshold_window(&mut self, threshold: u16) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        convert_and_write_u16(self, constants::registers::H_THRESH_REG, threshold)
     }
 
-    pub fn get_power_saving_mode(&mut self) -> Result<PowerSavingMode, LightSensorI2cError> {
-        let state = read_and_convert_to_u16(self, constants::registers::POWER_SAVE_REG).unwrap();
-        let mode = clip_u16(state, 1, 2);
-        
-        Ok(mode.into())
+    pub fn get_low_threshold_window(&mut self) -> Result<u16, LightSensorI2cError<I2C::Error>> {
+        read_and_convert_to_u16(self, constants::registers::L_THRESH_REG)
     }
 
-    pub fn set_power_saving_mode(&mut self, mode: PowerSavingMode) -> Result<(), LightSensorI2cError> {
-        let old_state = read_and_convert_to_u16(self, constants::registers::POWER_SAVE_REG).unwrap();
-        let new_state = insert_u16(old_state, 1, 2, mode.into());
-        convert_and_write_u16(self, constants::registers::POWER_SAVE_REG, new_state).unwrap();
+    pub fn set_low_threshold_window(&mut self, threshold: u16) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        convert_and_write_u16(self, constants::registers::L_THRESH_REG, threshold)
+    }
 
-        Ok(())
+    pub fn get_power_saving_mode(&mut self) -> Result<PowerSavingMode, LightSensorI2cError<I2C::Error>> {
+        read_field(self, bitfield::POWER_SAVING_MODE)
     }
 
-    pub fn get_power_saving_mode_enabled(&mut self) -> Result<PowerSavingModeEnable, LightSensorI2cError> {
-        let state = read_and_convert_to_u16(self, constants::registers::POWER_SAVE_REG).unwrap();
-        let enabled = clip_u16(state, 0, 1);
+    pub fn set_power_saving_mode(&mut self, mode: PowerSavingMode) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        write_field(self, bitfield::POWER_SAVING_MODE, mode)
+    }
 
-        Ok(enabled.into())
+    pub fn get_power_saving_mode_enabled(&mut self) -> Result<PowerSavingModeEnable, LightSensorI2cError<I2C::Error>> {
+        read_field(self, bitfield::POWER_SAVING_MODE_ENABLE)
     }
 
-    pub fn set_power_saving_mode_enabled(&mut self, enable: PowerSavingModeEnable) -> Result<(), LightSensorI2cError> {
-        let old_state = read_and_convert_to_u16(self, constants::registers::POWER_SAVE_REG).unwrap();
-        let new_state = insert_u16(old_state, 0, 1, enable.into());
-        convert_and_write_u16(self, constants::registers::POWER_SAVE_REG, new_state).unwrap();
+    pub fn set_power_saving_mode_enabled(&mut self, enable: PowerSavingModeEnable) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        write_field(self, bitfield::POWER_SAVING_MODE_ENABLE, enable)
+    }
 
-        Ok(())
+    pub fn get_ambient_light_output(&mut self) -> Result<u16, LightSensorI2cError<I2C::Error>> {
+        read_and_convert_to_u16(self, constants::registers::AMBIENT_LIGHT_DATA_REG)
     }
 
-    pub fn get_ambient_light_output(&mut self) -> Result<u16, LightSensorI2cError> {
-        let alo = read_and_convert_to_u16(self, constants::registers::AMBIENT_LIGHT_DATA_REG).unwrap();
+    pub fn get_white_light_output(&mut self) -> Result<u16, LightSensorI2cError<I2C::Error>> {
+        read_and_convert_to_u16(self, constants::registers::WHITE_LIGHT_DATA_REG)
+    }
 
-        Ok(alo)
-    } 
+    /// Read both threshold flags with a single access. Reading the interrupt
+    /// register clears it, so the flags must not be read separately.
+    pub fn read_interrupt_status(&mut self) -> Result<InterruptStatus, LightSensorI2cError<I2C::Error>> {
+        let state = read_and_convert_to_u16(self, constants::registers::INTERRUPT_REG)?;
 
-    pub fn get_white_light_output(&mut self) -> Result<u16, LightSensorI2cError> {
-        let wlo = read_and_convert_to_u16(self, constants::registers::WHITE_LIGHT_DATA_REG).unwrap();
-        
-        Ok(wlo)
+        Ok(InterruptStatus::from_word(state)?)
     }
 
-    pub fn get_low_threshold_exceeded(&mut self) -> Result<Threshold, LightSensorI2cError> {
-        let state = read_and_convert_to_u16(self, constants::registers::INTERRUPT_REG).unwrap();
-        let threshold_exceeded = clip_u16(state, 15, 1);
-        
-        Ok(threshold_exceeded.into())
+    #[deprecated(note = "clears the high flag too; use `read_interrupt_status`")]
+    pub fn get_low_threshold_exceeded(&mut self) -> Result<Threshold, LightSensorI2cError<I2C::Error>> {
+        Ok(self.read_interrupt_status()?.low)
     }
 
-    pub fn get_high_threshold_exceeded(&mut self) -> Result<Threshold, LightSensorI2cError> {
-        let state = read_and_convert_to_u16(self, constants::registers::INTERRUPT_REG).unwrap();
-        let threshold_exceeded = clip_u16(state, 14, 1);
-        
-        Ok(threshold_exceeded.into())
+    #[deprecated(note = "clears the low flag too; use `read_interrupt_status`")]
+    pub fn get_high_threshold_exceeded(&mut self) -> Result<Threshold, LightSensorI2cError<I2C::Error>> {
+        Ok(self.read_interrupt_status()?.high)
     }
 
-}
-
+    /// Read the part code and address option from the ID register.
+    pub fn read_device_id(&mut self) -> Result<DeviceId, LightSensorI2cError<I2C::Error>> {
+        let state = read_and_convert_to_u16(self, constants::registers::ID_REG)?;
 
-/// Get value from a specific register in sensor.
-pub fn read_from_register<I2C: I2c, Delay: DelayNs>(dev: &mut LightSensorI2c<I2C, Delay> , register: u8, buffer: &mut [u8]) -> Result<(), LightSensorI2cError> {
-    match dev.i2c.write_read(dev.address, &[register], buffer) {
-        Ok(_) => Ok(()),
-        Err(_) => Err(LightSensorI2cError::IOError)
+        Ok(DeviceId::from_word(state))
     }
-}
 
-/// Set value from a specific register in sensor.
-pub fn write_to_register<I2C: I2c, Delay: DelayNs>(dev: &mut LightSensorI2c<I2C, Delay>, register: u8, bytes: &[u8]) -> Result<(), LightSensorI2cError> {
-    let mut buffer = Vec::<u8>::with_capacity(1+bytes.len());
-    buffer.push(register);
-    for value in bytes {
-        buffer.push(*value);
+    /// Read a whole register, from the shadow copy when it holds one.
+    pub(crate) fn read_word(&mut self, register: u8) -> Result<u16, LightSensorI2cError<I2C::Error>> {
+        match self.shadow.get(register) {
+            Some(state) => Ok(state),
+            None => read_and_convert_to_u16(self, register),
+        }
     }
-    // TODO check if it matches write_bytes
-    match dev.i2c.write(dev.address, &buffer) {
-        Ok(_) => Ok(()),
-        Err(_) => Err(LightSensorI2cError::IOError)
+
+    /// Write a whole register, skipping the write when the shadow copy
+    /// shows it already holds `state`.
+    pub(crate) fn write_word(&mut self, register: u8, state: u16) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        if self.shadow.get(register) == Some(state) {
+            return Ok(());
+        }
+        convert_and_write_u16(self, register, state)?;
+        self.shadow.update(register, state);
+
+        Ok(())
     }
-}
 
+    pub(crate) fn delay_ms(&mut self, ms: u32) {
+        self.delay.delay_ms(ms);
+    }
 
-fn read_and_convert_to_u16<I2C: I2c, Delay: DelayNs>(dev: &mut LightSensorI2c<I2C, Delay>, register: u8) -> Result<u16, LightSensorI2cError> {
-    let mut buffer = [0u8; 2];
-    read_from_register(dev, register, &mut buffer).unwrap();
-    let state = convert_buffer_to_u16(&buffer).unwrap();
-    Ok(state)
 }
 
-fn convert_and_write_u16<I2C: I2c, Delay: DelayNs>(dev: &mut LightSensorI2c<I2C, Delay>, register: u8, state: u16) -> Result<(), LightSensorI2cError> {
-    let mut buffer = [0u8; 2];
-    convert_u16_to_buffer(&mut buffer, state).unwrap();
-    write_to_register(dev, register, &buffer).unwrap();
-    Ok(())
-}
 
-fn convert_buffer_to_u16(buffer: &[u8]) -> Result<u16, ()> {
-let num = LittleEndian::read_u16(buffer);
-Ok(num)
+/// Get value from a specific register in sensor.
+pub fn read_from_register<I2C: I2c, Delay: DelayNs, V: ChipVariant>(dev: &mut LightSensorI2c<I2C, Delay, V>, register: u8, buffer: &mut [u8]) -> Result<(), LightSensorI2cError<I2C::Error>> {
+    dev.i2c.write_read(dev.address, &[register], buffer).map_err(LightSensorI2cError::from_bus)
 }
 
-fn convert_u16_to_buffer(buffer: &mut [u8], num: u16) -> Result<(), ()> {
-LittleEndian::write_u16(buffer, num);
-Ok(())
+/// Set value from a specific register in sensor.
+pub fn write_to_register<I2C: I2c, Delay: DelayNs, V: ChipVariant>(dev: &mut LightSensorI2c<I2C, Delay, V>, register: u8, bytes: &[u8; 2]) -> Result<(), LightSensorI2cError<I2C::Error>> {
+    let buffer = [register, bytes[0], bytes[1]];
+    dev.i2c.write(dev.address, &buffer).map_err(LightSensorI2cError::from_bus)
 }
 
-fn clip_u16(state: u16, trailing_zeros: u16, length: u16) -> u16 {
-let mask = create_mask(trailing_zeros, length);
-(state & mask) >> trailing_zeros
+
+fn read_and_convert_to_u16<I2C: I2c, Delay: DelayNs, V: ChipVariant>(dev: &mut LightSensorI2c<I2C, Delay, V>, register: u8) -> Result<u16, LightSensorI2cError<I2C::Error>> {
+    let mut buffer = [0u8; 2];
+    read_from_register(dev, register, &mut buffer)?;
+    Ok(bitfield::read_buffer(&buffer))
 }
 
-fn insert_u16(state: u16, trailing_zeros: u16, length: u16, value: u16) -> u16 {
-let mask = create_mask(trailing_zeros, length);
-(state & !mask) | ((value << trailing_zeros) & mask)
+fn convert_and_write_u16<I2C: I2c, Delay: DelayNs, V: ChipVariant>(dev: &mut LightSensorI2c<I2C, Delay, V>, register: u8, state: u16) -> Result<(), LightSensorI2cError<I2C::Error>> {
+    let buffer = bitfield::write_buffer(register, state);
+    dev.i2c.write(dev.address, &buffer).map_err(LightSensorI2cError::from_bus)
 }
 
-fn create_mask(trailing_zeros: u16, length: u16) -> u16 {
-let mut mask = 0u16;
-for _ in 0..length {
-    mask = (mask << 1) + 1u16
+fn read_field<I2C: I2c, Delay: DelayNs, V: ChipVariant, T: TryFrom<u16, Error = DecodeError>>(dev: &mut LightSensorI2c<I2C, Delay, V>, field: bitfield::Field) -> Result<T, LightSensorI2cError<I2C::Error>> {
+    let state = dev.read_word(field.register)?;
+    Ok(field.decode(state)?)
 }
-mask << trailing_zeros
 
+fn write_field<I2C: I2c, Delay: DelayNs, V: ChipVariant>(dev: &mut LightSensorI2c<I2C, Delay, V>, field: bitfield::Field, value: impl Into<u16>) -> Result<(), LightSensorI2cError<I2C::Error>> {
+    let old_state = dev.read_word(field.register)?;
+    let new_state = field.encode(old_state, value);
+    dev.write_word(field.register, new_state)
 }
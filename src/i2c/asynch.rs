@@ -0,0 +1,241 @@
+// Public imports
+use core::marker::PhantomData;
+use embedded_hal_async::{i2c::I2c, delay::DelayNs};
+
+// Local imports
+use crate::variant::{ChipVariant, Veml7700};
+use super::{bitfield, constants, DecodeError, Destroyed, DeviceId, LightSensorI2cError, POWER_ON_DELAY_MS};
+use super::{Gain, IntegrationTime, InterruptEnable, InterruptStatus, PersistenceProtectNumber, PowerSavingMode, PowerSavingModeEnable, Shutdown, Threshold};
+
+
+/// Async I2C wrapper for LightSensor
+pub struct LightSensorI2c<I2C, Delay, V = Veml7700>{
+    i2c: I2C,
+    address: u8,
+    delay: Delay,
+    shadow: bitfield::Shadow,
+    variant: PhantomData<V>,
+}
+
+
+impl<I2C: I2c, Delay: DelayNs> LightSensorI2c<I2C, Delay>{
+    /// Create new async LightSensorI2c.
+    pub fn new(i2c: I2C, address: u8, delay: Delay) -> LightSensorI2c<I2C, Delay> {
+        Self::with_variant(i2c, address, delay, Veml7700)
+    }
+}
+
+
+impl<I2C: I2c, Delay: DelayNs, V: ChipVariant> LightSensorI2c<I2C, Delay, V>{
+    /// Create new LightSensorI2c for the part `V`.
+    pub fn with_variant(i2c: I2C, address: u8, delay: Delay, _variant: V) -> LightSensorI2c<I2C, Delay, V> {
+        LightSensorI2c { i2c, address, delay, shadow: bitfield::Shadow::default(), variant: PhantomData }
+    }
+
+    /// Address the driver talks to.
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Give back the bus and the delay, leaving the sensor as it is.
+    pub fn destroy(self) -> (I2C, Delay) {
+        (self.i2c, self.delay)
+    }
+
+    /// Shut the sensor down, then give back the bus and the delay.
+    ///
+    /// On a bus error the driver is handed back along with it.
+    pub async fn destroy_powered_off(mut self) -> Destroyed<I2C, Delay, Self, LightSensorI2cError<I2C::Error>> {
+        match self.set_shutdown(Shutdown::PowerOff).await {
+            Ok(()) => Ok(self.destroy()),
+            Err(error) => Err((self, error)),
+        }
+    }
+
+    /// Read the configuration and power-save registers into the driver's
+    /// shadow copy. Until [`invalidate`](Self::invalidate) is called, field
+    /// reads are served from the copy and field writes skip the read.
+    pub async fn sync_from_device(&mut self) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        for register in [constants::registers::SETTING_REG, constants::registers::POWER_SAVE_REG] {
+            let state = read_and_convert_to_u16(self, register).await?;
+            self.shadow.set(register, state);
+        }
+
+        Ok(())
+    }
+
+    /// Drop the shadow copy, so every access goes to the bus again.
+    pub fn invalidate(&mut self) {
+        self.shadow.clear();
+    }
+
+    pub async fn get_gain(&mut self) -> Result<Gain, LightSensorI2cError<I2C::Error>> {
+        let state = self.read_word(constants::registers::SETTING_REG).await?;
+
+        Ok(V::decode_gain(state)?)
+    }
+
+    pub async fn set_gain(&mut self, gain: Gain) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        let old_state = self.read_word(constants::registers::SETTING_REG).await?;
+        let new_state = V::encode_gain(old_state, gain);
+        self.write_word(constants::registers::SETTING_REG, new_state).await
+    }
+
+    pub async fn get_integration_time(&mut self) -> Result<IntegrationTime, LightSensorI2cError<I2C::Error>> {
+        read_field(self, bitfield::INTEGRATION_TIME).await
+    }
+
+    pub async fn set_integration_time(&mut self, integration_time: IntegrationTime) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        write_field(self, bitfield::INTEGRATION_TIME, integration_time).await
+    }
+
+    /// Set gain and integration time with a single register write.
+    pub async fn set_scale(&mut self, gain: Gain, integration_time: IntegrationTime) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        let old_state = self.read_word(constants::registers::SETTING_REG).await?;
+        let new_state = bitfield::INTEGRATION_TIME.encode(V::encode_gain(old_state, gain), integration_time);
+        self.write_word(constants::registers::SETTING_REG, new_state).await
+    }
+
+    pub async fn get_persist_protect_number(&mut self) -> Result<PersistenceProtectNumber, LightSensorI2cError<I2C::Error>> {
+        read_field(self, bitfield::PERSIST_PROTECT_NUMBER).await
+    }
+
+    pub async fn set_persist_protect_number(&mut self, persistence_protect_number: PersistenceProtectNumber) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        write_field(self, bitfield::PERSIST_PROTECT_NUMBER, persistence_protect_number).await
+    }
+
+    pub async fn get_interrupt_enabled(&mut self) -> Result<InterruptEnable, LightSensorI2cError<I2C::Error>> {
+        read_field(self, bitfield::INTERRUPT_ENABLE).await
+    }
+
+    pub async fn set_interrupt_enabled(&mut self, interrupt_enable: InterruptEnable) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        write_field(self, bitfield::INTERRUPT_ENABLE, interrupt_enable).await
+    }
+
+    pub async fn get_shutdown(&mut self) -> Result<Shutdown, LightSensorI2cError<I2C::Error>> {
+        read_field(self, bitfield::SHUTDOWN).await
+    }
+
+    pub async fn set_shutdown(&mut self, shutdown: Shutdown) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        write_field(self, bitfield::SHUTDOWN, shutdown).await?;
+
+        if shutdown == Shutdown::PowerOn {
+            self.delay.delay_ms(POWER_ON_DELAY_MS).await;
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_high_threshold_window(&mut self) -> Result<u16, LightSensorI2cError<I2C::Error>> {
+        read_and_convert_to_u16(self, constants::registers::H_THRESH_REG).await
+    }
+
+    pub async fn set_high_threshold_window(&mut self, threshold: u16) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        convert_and_write_u16(self, constants::registers::H_THRESH_REG, threshold).await
+    }
+
+    pub async fn get_low_threshold_window(&mut self) -> Result<u16, LightSensorI2cError<I2C::Error>> {
+        read_and_convert_to_u16(self, constants::registers::L_THRESH_REG).await
+    }
+
+    pub async fn set_low_threshold_window(&mut self, threshold: u16) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        convert_and_write_u16(self, constants::registers::L_THRESH_REG, threshold).await
+    }
+
+    pub async fn get_power_saving_mode(&mut self) -> Result<PowerSavingMode, LightSensorI2cError<I2C::Error>> {
+        read_field(self, bitfield::POWER_SAVING_MODE).await
+    }
+
+    pub async fn set_power_saving_mode(&mut self, mode: PowerSavingMode) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        write_field(self, bitfield::POWER_SAVING_MODE, mode).await
+    }
+
+    pub async fn get_power_saving_mode_enabled(&mut self) -> Result<PowerSavingModeEnable, LightSensorI2cError<I2C::Error>> {
+        read_field(self, bitfield::POWER_SAVING_MODE_ENABLE).await
+    }
+
+    pub async fn set_power_saving_mode_enabled(&mut self, enable: PowerSavingModeEnable) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        write_field(self, bitfield::POWER_SAVING_MODE_ENABLE, enable).await
+    }
+
+    pub async fn get_ambient_light_output(&mut self) -> Result<u16, LightSensorI2cError<I2C::Error>> {
+        read_and_convert_to_u16(self, constants::registers::AMBIENT_LIGHT_DATA_REG).await
+    }
+
+    pub async fn get_white_light_output(&mut self) -> Result<u16, LightSensorI2cError<I2C::Error>> {
+        read_and_convert_to_u16(self, constants::registers::WHITE_LIGHT_DATA_REG).await
+    }
+
+    /// Read both threshold flags with a single access. Reading the interrupt
+    /// register clears it, so the flags must not be read separately.
+    pub async fn read_interrupt_status(&mut self) -> Result<InterruptStatus, LightSensorI2cError<I2C::Error>> {
+        let state = read_and_convert_to_u16(self, constants::registers::INTERRUPT_REG).await?;
+
+        Ok(InterruptStatus::from_word(state)?)
+    }
+
+    #[deprecated(note = "clears the high flag too; use `read_interrupt_status`")]
+    pub async fn get_low_threshold_exceeded(&mut self) -> Result<Threshold, LightSensorI2cError<I2C::Error>> {
+        Ok(self.read_interrupt_status().await?.low)
+    }
+
+    #[deprecated(note = "clears the low flag too; use `read_interrupt_status`")]
+    pub async fn get_high_threshold_exceeded(&mut self) -> Result<Threshold, LightSensorI2cError<I2C::Error>> {
+        Ok(self.read_interrupt_status().await?.high)
+    }
+
+    /// Read the part code and address option from the ID register.
+    pub async fn read_device_id(&mut self) -> Result<DeviceId, LightSensorI2cError<I2C::Error>> {
+        let state = read_and_convert_to_u16(self, constants::registers::ID_REG).await?;
+
+        Ok(DeviceId::from_word(state))
+    }
+
+    /// Read a whole register, from the shadow copy when it holds one.
+    pub(crate) async fn read_word(&mut self, register: u8) -> Result<u16, LightSensorI2cError<I2C::Error>> {
+        match self.shadow.get(register) {
+            Some(state) => Ok(state),
+            None => read_and_convert_to_u16(self, register).await,
+        }
+    }
+
+    /// Write a whole register, skipping the write when the shadow copy
+    /// shows it already holds `state`.
+    pub(crate) async fn write_word(&mut self, register: u8, state: u16) -> Result<(), LightSensorI2cError<I2C::Error>> {
+        if self.shadow.get(register) == Some(state) {
+            return Ok(());
+        }
+        convert_and_write_u16(self, register, state).await?;
+        self.shadow.update(register, state);
+
+        Ok(())
+    }
+
+    pub(crate) async fn delay_ms(&mut self, ms: u32) {
+        self.delay.delay_ms(ms).await;
+    }
+
+}
+
+
+async fn read_and_convert_to_u16<I2C: I2c, Delay: DelayNs, V: ChipVariant>(dev: &mut LightSensorI2c<I2C, Delay, V>, register: u8) -> Result<u16, LightSensorI2cError<I2C::Error>> {
+    let mut buffer = [0u8; 2];
+    dev.i2c.write_read(dev.address, &[register], &mut buffer).await.map_err(LightSensorI2cError::from_bus)?;
+    Ok(bitfield::read_buffer(&buffer))
+}
+
+async fn convert_and_write_u16<I2C: I2c, Delay: DelayNs, V: ChipVariant>(dev: &mut LightSensorI2c<I2C, Delay, V>, register: u8, state: u16) -> Result<(), LightSensorI2cError<I2C::Error>> {
+    let buffer = bitfield::write_buffer(register, state);
+    dev.i2c.write(dev.address, &buffer).await.map_err(LightSensorI2cError::from_bus)
+}
+
+async fn read_field<I2C: I2c, Delay: DelayNs, V: ChipVariant, T: TryFrom<u16, Error = DecodeError>>(dev: &mut LightSensorI2c<I2C, Delay, V>, field: bitfield::Field) -> Result<T, LightSensorI2cError<I2C::Error>> {
+    let state = dev.read_word(field.register).await?;
+    Ok(field.decode(state)?)
+}
+
+async fn write_field<I2C: I2c, Delay: DelayNs, V: ChipVariant>(dev: &mut LightSensorI2c<I2C, Delay, V>, field: bitfield::Field, value: impl Into<u16>) -> Result<(), LightSensorI2cError<I2C::Error>> {
+    let old_state = dev.read_word(field.register).await?;
+    let new_state = field.encode(old_state, value);
+    dev.write_word(field.register, new_state).await
+}
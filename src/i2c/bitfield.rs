@@ -0,0 +1,113 @@
+// Register layout shared by the blocking and async drivers.
+
+// Public imports
+use byteorder::{ByteOrder, LittleEndian};
+
+// Local imports
+use super::constants::registers;
+use super::DecodeError;
+
+
+/// Position of a setting inside a 16-bit register.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) struct Field {
+    pub register: u8,
+    pub trailing_zeros: u16,
+    pub length: u16,
+}
+
+pub(crate) const GAIN: Field = Field { register: registers::SETTING_REG, trailing_zeros: 11, length: 2 };
+pub(crate) const INTEGRATION_TIME: Field = Field { register: registers::SETTING_REG, trailing_zeros: 6, length: 4 };
+pub(crate) const PERSIST_PROTECT_NUMBER: Field = Field { register: registers::SETTING_REG, trailing_zeros: 4, length: 2 };
+pub(crate) const INTERRUPT_ENABLE: Field = Field { register: registers::SETTING_REG, trailing_zeros: 1, length: 1 };
+pub(crate) const SHUTDOWN: Field = Field { register: registers::SETTING_REG, trailing_zeros: 0, length: 1 };
+pub(crate) const POWER_SAVING_MODE: Field = Field { register: registers::POWER_SAVE_REG, trailing_zeros: 1, length: 2 };
+pub(crate) const POWER_SAVING_MODE_ENABLE: Field = Field { register: registers::POWER_SAVE_REG, trailing_zeros: 0, length: 1 };
+pub(crate) const LOW_THRESHOLD_EXCEEDED: Field = Field { register: registers::INTERRUPT_REG, trailing_zeros: 15, length: 1 };
+pub(crate) const HIGH_THRESHOLD_EXCEEDED: Field = Field { register: registers::INTERRUPT_REG, trailing_zeros: 14, length: 1 };
+
+impl Field {
+    /// Decode the field out of a full register word.
+    pub fn decode<T: TryFrom<u16, Error = DecodeError>>(self, state: u16) -> Result<T, DecodeError> {
+        T::try_from(clip_u16(state, self.trailing_zeros, self.length)).map_err(|error| DecodeError { value: state, ..error })
+    }
+
+    /// Replace the field inside a full register word.
+    pub fn encode(self, state: u16, value: impl Into<u16>) -> u16 {
+        insert_u16(state, self.trailing_zeros, self.length, value.into())
+    }
+}
+
+
+/// Host-side copy of the configuration and power-save registers.
+///
+/// Empty until synced; while empty every access goes to the bus.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub(crate) struct Shadow {
+    setting: Option<u16>,
+    power_save: Option<u16>,
+}
+
+impl Shadow {
+    /// Cached value of `register`, if it is shadowed and synced.
+    pub fn get(&self, register: u8) -> Option<u16> {
+        match register {
+            registers::SETTING_REG => self.setting,
+            registers::POWER_SAVE_REG => self.power_save,
+            _ => None,
+        }
+    }
+
+    /// Record a value read from or written to the device.
+    pub fn set(&mut self, register: u8, state: u16) {
+        match register {
+            registers::SETTING_REG => self.setting = Some(state),
+            registers::POWER_SAVE_REG => self.power_save = Some(state),
+            _ => (),
+        }
+    }
+
+    /// Record a value written to the device, if the register is already synced.
+    pub fn update(&mut self, register: u8, state: u16) {
+        if self.get(register).is_some() {
+            self.set(register, state);
+        }
+    }
+
+    /// Drop every cached value.
+    pub fn clear(&mut self) {
+        *self = Shadow::default();
+    }
+}
+
+
+/// Build the bytes of a register write.
+pub(crate) fn write_buffer(register: u8, state: u16) -> [u8; 3] {
+    let mut buffer = [register, 0, 0];
+    LittleEndian::write_u16(&mut buffer[1..], state);
+    buffer
+}
+
+/// Convert the bytes of a register read.
+pub(crate) fn read_buffer(buffer: &[u8; 2]) -> u16 {
+    LittleEndian::read_u16(buffer)
+}
+
+
+fn clip_u16(state: u16, trailing_zeros: u16, length: u16) -> u16 {
+    let mask = create_mask(trailing_zeros, length);
+    (state & mask) >> trailing_zeros
+}
+
+fn insert_u16(state: u16, trailing_zeros: u16, length: u16, value: u16) -> u16 {
+    let mask = create_mask(trailing_zeros, length);
+    (state & !mask) | ((value << trailing_zeros) & mask)
+}
+
+fn create_mask(trailing_zeros: u16, length: u16) -> u16 {
+    let mut mask = 0u16;
+    for _ in 0..length {
+        mask = (mask << 1) + 1u16
+    }
+    mask << trailing_zeros
+}
@@ -6,6 +6,7 @@ pub mod registers {
     pub const AMBIENT_LIGHT_DATA_REG : u8 = 0x04;
     pub const WHITE_LIGHT_DATA_REG : u8 = 0x05;
     pub const INTERRUPT_REG : u8 = 0x06;
+    pub const ID_REG : u8 = 0x07;
 }
 
 pub mod addresses {
@@ -1,148 +1,597 @@
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+
 // Local modules
+#[macro_use]
+mod fmt;
+pub mod aggregate;
+pub mod calibration;
+pub mod config;
+pub mod filter;
 pub mod i2c;
+pub mod measurement;
+pub mod power;
+pub mod range;
+pub mod resolution;
+pub mod storage;
+pub mod variant;
+#[cfg(feature = "async")]
+pub mod asynch;
 
 // Public imports
-use embedded_hal::{delay::DelayNs, i2c::I2c};
+use core::fmt::{Debug, Display, Formatter};
+use embedded_hal::{delay::DelayNs, digital::{self, InputPin}, i2c::{Error, ErrorKind, I2c}};
 
 // Local imports
-pub use i2c::Address;
+use i2c::constants;
+pub use aggregate::{Aggregate, Strategy};
+pub use calibration::{Calibration, CalibrationFit};
+pub use config::{Config, ConfigBuilder};
+pub use filter::{Ema, Kalman, MovingWindow};
+pub use i2c::{Address, AddressError};
+pub use measurement::Measurement;
+pub use power::PowerPlan;
+pub use range::AutoRange;
+pub use resolution::Resolution;
+pub use storage::StorageError;
+#[cfg(feature = "serde")]
+pub use storage::Versioned;
+pub use variant::{ChipVariant, Veml6030, Veml6035, Veml7700};
+
+
+/// Errors returned by [`LightSensor`], generic over the bus error `E`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LightSensorError<E> {
+    /// The bus transaction failed.
+    Bus(E),
+    /// No device acknowledged the address.
+    NotPresent(E),
+    /// A register held a value that does not map to a known setting.
+    InvalidValue { register: u8, value: u16 },
+    /// A device answered, but its ID register holds another part code.
+    WrongDevice { id: u8 },
+    /// A lux threshold is negative, beyond full scale, or the low limit is above the high one.
+    ThresholdOutOfRange,
+    /// Reading the interrupt pin failed.
+    Pin(digital::ErrorKind),
+    /// A measurement was polled without being started.
+    NotStarted,
+}
+
+impl<E: Error> LightSensorError<E> {
+    /// Kind of the underlying bus error, if the error came from the bus.
+    pub fn kind(&self) -> Option<ErrorKind> {
+        match self {
+            Self::Bus(error) | Self::NotPresent(error) => Some(error.kind()),
+            Self::InvalidValue { .. } | Self::WrongDevice { .. } | Self::ThresholdOutOfRange | Self::Pin(_) | Self::NotStarted => None,
+        }
+    }
+}
 
+impl<E> From<i2c::DecodeError> for LightSensorError<E> {
+    fn from(error: i2c::DecodeError) -> Self {
+        Self::InvalidValue { register: error.register, value: error.value }
+    }
+}
 
-// Constants for conversion
-const C0: f64 = 1.0023;
-const C1: f64 = 8.1488e-05;
-const C2: f64 = -9.3924e-09;
-const C3: f64 = 6.0135e-13;
+impl<E: Debug> Display for LightSensorError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Bus(error) => write!(f, "bus error: {:?}", error),
+            Self::NotPresent(error) => write!(f, "device not present: {:?}", error),
+            Self::InvalidValue { register, value } => write!(f, "invalid value {:#06x} in register {:#04x}", value, register),
+            Self::WrongDevice { id } => write!(f, "unexpected device id {:#04x}", id),
+            Self::ThresholdOutOfRange => write!(f, "threshold outside the representable range"),
+            Self::Pin(kind) => write!(f, "interrupt pin error: {:?}", kind),
+            Self::NotStarted => write!(f, "no measurement started"),
+        }
+    }
+}
 
+#[cfg(feature = "std")]
+impl<E: Debug> std::error::Error for LightSensorError<E> {}
 
-#[derive(Debug)]
-pub enum LightSensorError {
-    ConversionError,
-    IOError
+impl<E> From<i2c::LightSensorI2cError<E>> for LightSensorError<E> {
+    fn from(error: i2c::LightSensorI2cError<E>) -> Self {
+        match error {
+            i2c::LightSensorI2cError::Bus(error) => Self::Bus(error),
+            i2c::LightSensorI2cError::NotPresent(error) => Self::NotPresent(error),
+            i2c::LightSensorI2cError::InvalidValue { register, value } => Self::InvalidValue { register, value },
+        }
+    }
 }
 
 
-pub struct LightSensor<I2C, Delay> {
-    dev: i2c::LightSensorI2c<I2C, Delay>
+/// Threshold crossing reported through the interrupt line.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ThresholdEvent {
+    /// The high threshold was exceeded.
+    pub high: bool,
+    /// The low threshold was exceeded.
+    pub low: bool,
+    /// Ambient light counts read right after the event.
+    pub raw: u16,
+    /// `raw` converted to lux.
+    pub lux: f32,
+}
+
+
+/// Shorthand for [`Config::builder`], callable without naming the bus types.
+impl LightSensor<(), ()> {
+    /// Start building a [`Config`] for [`apply_config`](LightSensor::apply_config).
+    pub fn builder() -> ConfigBuilder {
+        Config::builder()
+    }
+}
+
+pub struct LightSensor<I2C, Delay, V = Veml7700> {
+    dev: i2c::LightSensorI2c<I2C, Delay, V>,
+    auto_range: AutoRange,
+    calibration: Calibration,
+    /// Threshold window in lux, re-applied whenever the scale changes.
+    threshold_window: Option<(f32, f32)>,
+    /// Settings changed without a full refresh time since.
+    settings_changed: bool,
+    /// Start time and refresh time of a measurement being polled, in ms.
+    pending_measurement: Option<(u32, u32)>,
 }
 
 impl<I2C: I2c, Delay: DelayNs> LightSensor<I2C, Delay> {
     pub fn new(dev: I2C, address: Address, delay: Delay) -> Self {
-        let i2c_wrapper = i2c::LightSensorI2c::new(dev, address.into(), delay);
-        LightSensor{dev: i2c_wrapper}
+        Self::with_variant(dev, address, delay, Veml7700)
     }
 
-    pub fn build(dev: I2C, address: Address, delay: Delay) -> LightSensor<I2C, Delay> {
-        let mut sensor = Self::new(dev, address.into(), delay);
+    pub fn build(dev: I2C, address: Address, delay: Delay) -> Result<LightSensor<I2C, Delay>, LightSensorError<I2C::Error>> {
+        let mut sensor = Self::new(dev, address, delay);
+        sensor.apply_config(&Config::default())?;
 
-        sensor.dev.set_shutdown(i2c::Shutdown::PowerOn).unwrap();
-        sensor.dev.set_gain(i2c::Gain::X1_4).unwrap();
-        sensor.dev.set_integration_time(i2c::IntegrationTime::Ms50).unwrap();
+        Ok(sensor)
+    }
+}
 
-        sensor
+impl<I2C: I2c, Delay: DelayNs, V: ChipVariant> LightSensor<I2C, Delay, V> {
+    /// Create a LightSensor for the part `V`.
+    pub fn with_variant(dev: I2C, address: Address, delay: Delay, variant: V) -> Self {
+        let i2c_wrapper = i2c::LightSensorI2c::with_variant(dev, address.into(), delay, variant);
+        LightSensor{dev: i2c_wrapper, auto_range: AutoRange::default(), calibration: Calibration::for_variant::<V>(), threshold_window: None, settings_changed: true, pending_measurement: None}
     }
 
-    pub fn convert_raw_to_lux(&mut self, raw: u16) -> Result<f32, LightSensorError> {
-        const LX_BIT: f64 = 0.0288;
+    /// Write a whole configuration, one write per register.
+    ///
+    /// Thresholds and power saving go first so the sensor never runs with a
+    /// stale window; the configuration register, which powers it on, goes last.
+    /// The raw thresholds in `config` replace any window set in lux.
+    pub fn apply_config(&mut self, config: &Config) -> Result<(), LightSensorError<I2C::Error>> {
+        self.threshold_window = None;
+        self.mark_settings_changed();
+        self.dev.write_word(constants::registers::H_THRESH_REG, config.high_threshold)?;
+        self.dev.write_word(constants::registers::L_THRESH_REG, config.low_threshold)?;
+        self.dev.write_word(constants::registers::POWER_SAVE_REG, config.power_save_word())?;
+        self.dev.write_word(constants::registers::SETTING_REG, config.setting_word::<V>())?;
 
-        let gain = self.dev.get_gain().unwrap();
-        let integration_time = self.dev.get_integration_time().unwrap();
+        if config.shutdown == i2c::Shutdown::PowerOn {
+            self.dev.delay_ms(i2c::POWER_ON_DELAY_MS);
+        }
 
-        let it_factor: f64 = match integration_time {
-            i2c::IntegrationTime::Ms25 => 4.0,
-            i2c::IntegrationTime::Ms50 => 2.0,
-            i2c::IntegrationTime::Ms100 => 1.0,
-            i2c::IntegrationTime::Ms200 => 0.5,
-            i2c::IntegrationTime::Ms400 => 0.25,
-            i2c::IntegrationTime::Ms800 => 0.125
-        };
+        Ok(())
+    }
 
-        // lux = 
-        let gain_factor: f64 = match gain {
-            i2c::Gain::X2 => 1.0,
-            i2c::Gain::X1 => 2.0,
-            i2c::Gain::X1_4 => 8.0,
-            i2c::Gain::X1_8 => 16.0
-        };
+    /// Read the whole configuration back from the device.
+    pub fn read_config(&mut self) -> Result<Config, LightSensorError<I2C::Error>> {
+        let setting = self.dev.read_word(constants::registers::SETTING_REG)?;
+        let power_save = self.dev.read_word(constants::registers::POWER_SAVE_REG)?;
+        let high_threshold = self.dev.read_word(constants::registers::H_THRESH_REG)?;
+        let low_threshold = self.dev.read_word(constants::registers::L_THRESH_REG)?;
 
-        let mut lux = LX_BIT * it_factor * gain_factor * f64::from(raw);
-        println!("raw: {}", raw);
-        println!("it_factor {}", it_factor);
-        println!("gain_factor {}", gain_factor);
-        println!("lux {}", lux);
-
-        match gain {
-            i2c::Gain::X1_4 | i2c::Gain::X1_8 => {
-                // Compensate high lux
-                if lux > 1000.0 {
-                    lux = C3 * lux.powi(4) - C2 * lux.powi(3) + C1 * lux.powi(2) + C0 * lux;
-                }
-            },
-            _ => (),
-        };
+        Ok(Config::from_words::<V>(setting, power_save, high_threshold, low_threshold)?)
+    }
 
-        println!("raw: {}", raw);
-        println!("it_factor {}", it_factor);
-        println!("gain_factor {}", gain_factor);
-        println!("lux {}", lux);
+    /// Cache the configuration registers in the driver, so reads such as
+    /// [`convert_raw_to_lux`](Self::convert_raw_to_lux) stay off the bus.
+    pub fn sync_from_device(&mut self) -> Result<(), LightSensorError<I2C::Error>> {
+        Ok(self.dev.sync_from_device()?)
+    }
 
-        Ok(lux as f32)
+    /// Drop the cached configuration registers.
+    pub fn invalidate(&mut self) {
+        self.dev.invalidate();
+    }
 
+    /// Address the sensor is bound to.
+    pub fn address(&self) -> u8 {
+        self.dev.address()
     }
 
-    pub fn get_ambient_light_lux(&mut self) -> Result<f32, LightSensorError> {
-        let raw_lux = self.dev.get_ambient_light_output().unwrap();
+    /// Shut the sensor down until the next configuration write powers it on.
+    pub fn power_off(&mut self) -> Result<(), LightSensorError<I2C::Error>> {
+        self.dev.set_shutdown(i2c::Shutdown::PowerOff)?;
+        self.mark_settings_changed();
 
-        let lux = self.convert_raw_to_lux(raw_lux).unwrap();
-        Ok(lux)
+        Ok(())
+    }
+
+    /// Give back the bus and the delay, leaving the sensor as it is.
+    ///
+    /// Use [`destroy_powered_off`](Self::destroy_powered_off) to leave it shut down.
+    pub fn destroy(self) -> (I2C, Delay) {
+        self.dev.destroy()
+    }
+
+    /// Shut the sensor down, then give back the bus and the delay.
+    ///
+    /// On a bus error the driver is handed back along with it.
+    pub fn destroy_powered_off(mut self) -> i2c::Destroyed<I2C, Delay, Self, LightSensorError<I2C::Error>> {
+        match self.power_off() {
+            Ok(()) => Ok(self.destroy()),
+            Err(error) => Err((self, error)),
+        }
+    }
+
+    /// Read the ID register.
+    pub fn identify(&mut self) -> Result<i2c::DeviceId, LightSensorError<I2C::Error>> {
+        Ok(self.dev.read_device_id()?)
+    }
+
+    /// Check that the part `V` answers at the configured address.
+    ///
+    /// Fails with [`NotPresent`](LightSensorError::NotPresent) if nothing
+    /// acknowledges the address and with
+    /// [`WrongDevice`](LightSensorError::WrongDevice) if something else does.
+    pub fn probe(&mut self) -> Result<i2c::DeviceId, LightSensorError<I2C::Error>> {
+        let id = self.identify()?;
+        if id.device != V::DEVICE_ID {
+            return Err(LightSensorError::WrongDevice { id: id.device });
+        }
+
+        Ok(id)
+    }
+
+    /// Change the gain, keeping a lux threshold window at the same lux.
+    pub fn set_gain(&mut self, gain: i2c::Gain) -> Result<(), LightSensorError<I2C::Error>> {
+        self.dev.set_gain(gain)?;
+        self.mark_settings_changed();
+        self.refresh_threshold_window()
+    }
+
+    /// Change the integration time, keeping a lux threshold window at the same lux.
+    pub fn set_integration_time(&mut self, integration_time: i2c::IntegrationTime) -> Result<(), LightSensorError<I2C::Error>> {
+        self.dev.set_integration_time(integration_time)?;
+        self.mark_settings_changed();
+        self.refresh_threshold_window()
+    }
+
+    /// Set the interrupt threshold window in lux.
+    ///
+    /// The window is converted with the active gain and integration time, and
+    /// converted again whenever [`set_gain`](Self::set_gain),
+    /// [`set_integration_time`](Self::set_integration_time) or auto-ranging
+    /// change them.
+    pub fn set_threshold_window_lux(&mut self, low: f32, high: f32) -> Result<(), LightSensorError<I2C::Error>> {
+        if low > high {
+            return Err(LightSensorError::ThresholdOutOfRange);
+        }
+
+        let resolution = self.resolution()?;
+        let low_raw = resolution.raw_for_lux(low).ok_or(LightSensorError::ThresholdOutOfRange)?;
+        let high_raw = resolution.raw_for_lux(high).ok_or(LightSensorError::ThresholdOutOfRange)?;
+
+        self.dev.set_high_threshold_window(high_raw)?;
+        self.dev.set_low_threshold_window(low_raw)?;
+        self.threshold_window = Some((low, high));
 
-        // let gain = self.dev.get_gain().unwrap();
-        // let it = self.dev.get_integration_time().unwrap();
+        Ok(())
+    }
+
+    /// Read and clear both threshold flags with a single access.
+    pub fn read_interrupt_status(&mut self) -> Result<i2c::InterruptStatus, LightSensorError<I2C::Error>> {
+        Ok(self.dev.read_interrupt_status()?)
+    }
+
+    /// Block until `pin`, wired to the sensor's INT output, goes low, then
+    /// read the event behind it.
+    ///
+    /// The line is polled once per millisecond. Interrupts must be enabled,
+    /// and fire after [`PersistenceProtectNumber`](i2c::PersistenceProtectNumber)
+    /// consecutive readings outside the threshold window.
+    pub fn wait_for_threshold_event<P: InputPin>(&mut self, pin: &mut P) -> Result<ThresholdEvent, LightSensorError<I2C::Error>> {
+        while !pin.is_low().map_err(|error| LightSensorError::Pin(digital::Error::kind(&error)))? {
+            self.dev.delay_ms(1);
+        }
+
+        self.read_threshold_event()
+    }
 
-        // let factor = get_lux_raw_conversion_factor(it, gain);
-        // let lux = f64::from(raw_lux) * f64::from(factor);
-        // if (gain == i2c::Gain::X1_4 || gain == i2c::Gain::X1_8) && lux > 1000.0 {
-        //     Ok(compensate_high_lux(lux) as f32)
-        // } else {
-        //     Ok(lux as f32)
-        // }
+    /// Read the interrupt register once, which also clears it on the
+    /// device, together with the ambient light reading that caused it.
+    pub fn read_threshold_event(&mut self) -> Result<ThresholdEvent, LightSensorError<I2C::Error>> {
+        let status = self.dev.read_interrupt_status()?;
+        let raw = self.dev.get_ambient_light_output()?;
+        let lux = self.convert_raw_to_lux(raw)?;
 
+        Ok(ThresholdEvent {
+            high: status.high == i2c::Threshold::Exceeded,
+            low: status.low == i2c::Threshold::Exceeded,
+            raw,
+            lux,
+        })
+    }
+
+    /// Threshold window in lux, if one was set with [`set_threshold_window_lux`](Self::set_threshold_window_lux).
+    pub fn get_threshold_window_lux(&self) -> Option<(f32, f32)> {
+        self.threshold_window
+    }
+
+    /// Scale of the active gain and integration time, for converting
+    /// counts without further bus traffic.
+    pub fn resolution(&mut self) -> Result<Resolution<V>, LightSensorError<I2C::Error>> {
+        let gain = self.dev.get_gain()?;
+        let integration_time = self.dev.get_integration_time()?;
+
+        Ok(Resolution::for_variant(gain, integration_time).with_calibration(self.calibration))
+    }
+
+    /// Replace the calibration applied to every lux result, keeping a lux
+    /// threshold window at the same calibrated lux.
+    pub fn set_calibration(&mut self, calibration: Calibration) -> Result<(), LightSensorError<I2C::Error>> {
+        self.calibration = calibration;
+        self.refresh_threshold_window()
+    }
+
+    pub fn get_calibration(&self) -> Calibration {
+        self.calibration
+    }
+
+    pub fn convert_raw_to_lux(&mut self, raw: u16) -> Result<f32, LightSensorError<I2C::Error>> {
+        let lux = self.resolution()?.lux(raw);
+        debug!("raw {} lux {}", raw, lux);
+
+        Ok(lux)
+    }
+
+    pub fn get_ambient_light_lux(&mut self) -> Result<f32, LightSensorError<I2C::Error>> {
+        let raw_lux = self.dev.get_ambient_light_output()?;
+
+        let lux = self.convert_raw_to_lux(raw_lux)?;
+        Ok(lux)
     }
 
-    pub fn get_white_light(&mut self) -> Result<f32, LightSensorError> {
-        let white_light = self.dev.get_white_light_output().unwrap();
+    pub fn get_white_light(&mut self) -> Result<f32, LightSensorError<I2C::Error>> {
+        let white_light = self.dev.get_white_light_output()?;
 
         Ok(white_light as f32)
     }
 
+    /// Set the integration time and power-saving mode chosen by a [`PowerPlan`].
+    pub fn apply_power_plan(&mut self, plan: &PowerPlan) -> Result<(), LightSensorError<I2C::Error>> {
+        self.dev.set_integration_time(plan.integration_time)?;
+        if let Some(mode) = plan.mode {
+            self.dev.set_power_saving_mode(mode)?;
+        }
+        self.dev.set_power_saving_mode_enabled(plan.power_saving_mode_enable())?;
+        self.mark_settings_changed();
+        self.refresh_threshold_window()
+    }
+
+    /// Time between two samples under the current settings.
+    pub fn refresh_time_ms(&mut self) -> Result<u32, LightSensorError<I2C::Error>> {
+        let integration_time = self.dev.get_integration_time()?;
+        let mode = self.dev.get_power_saving_mode()?;
+        let enable = self.dev.get_power_saving_mode_enabled()?;
+
+        Ok(i2c::refresh_time_ms(integration_time, mode, enable))
+    }
+
+    /// Measure, first waiting one [refresh time](Self::refresh_time_ms) if
+    /// the settings changed since the last full refresh time, so the
+    /// counts never belong to the previous settings.
+    pub fn read_fresh(&mut self) -> Result<Measurement, LightSensorError<I2C::Error>> {
+        if self.settings_changed {
+            let refresh_time = self.refresh_time_ms()?;
+            debug!("waiting {} ms for a fresh sample", refresh_time);
+            self.dev.delay_ms(refresh_time);
+            self.settings_changed = false;
+        }
+
+        self.measure()
+    }
+
+    /// Read both channels, together with the settings and quality flags.
+    pub fn measure(&mut self) -> Result<Measurement, LightSensorError<I2C::Error>> {
+        let resolution = self.resolution()?;
+        let (als_raw, white_raw) = self.read_channels(true)?;
+
+        Ok(Measurement::new(&resolution, als_raw, white_raw, self.settings_changed))
+    }
+
+    /// Start a measurement without blocking, at `now_ms` on a monotonic
+    /// millisecond clock that may wrap.
+    ///
+    /// Finish it with [`poll_measurement`](Self::poll_measurement). The
+    /// sensor must be powered on; changing a setting in between drops the
+    /// measurement, and polling then fails with
+    /// [`NotStarted`](LightSensorError::NotStarted).
+    pub fn start_measurement(&mut self, now_ms: u32) -> Result<(), LightSensorError<I2C::Error>> {
+        let refresh_time = self.refresh_time_ms()?;
+        self.pending_measurement = Some((now_ms, refresh_time));
+
+        Ok(())
+    }
+
+    /// Read the measurement once a full refresh time has passed since
+    /// [`start_measurement`](Self::start_measurement), or return
+    /// [`WouldBlock`](nb::Error::WouldBlock) with no bus traffic until then.
+    pub fn poll_measurement(&mut self, now_ms: u32) -> nb::Result<Measurement, LightSensorError<I2C::Error>> {
+        let (started_ms, refresh_time) = self.pending_measurement.ok_or(nb::Error::Other(LightSensorError::NotStarted))?;
+        if now_ms.wrapping_sub(started_ms) < refresh_time {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        self.pending_measurement = None;
+        self.settings_changed = false;
+        Ok(self.measure()?)
+    }
+
+    /// Take `N` lux readings one [refresh time](Self::refresh_time_ms)
+    /// apart and combine them, e.g. to average out PWM-dimmed lighting.
+    ///
+    /// The first reading also waits if the settings just changed.
+    pub fn read_aggregated<const N: usize>(&mut self, strategy: Strategy) -> Result<Aggregate, LightSensorError<I2C::Error>> {
+        const { assert!(N > 0, "at least one reading is needed") };
+
+        let refresh_time = self.refresh_time_ms()?;
+        let resolution = self.resolution()?;
+        let mut readings = [0.0f32; N];
+        for (index, reading) in readings.iter_mut().enumerate() {
+            if index > 0 || self.settings_changed {
+                self.dev.delay_ms(refresh_time);
+                self.settings_changed = false;
+            }
+            *reading = resolution.lux(self.dev.get_ambient_light_output()?);
+        }
+
+        Ok(Aggregate::from_readings(&mut readings, strategy).expect("N is not zero"))
+    }
+
+    /// Power on, wait one integration period, read, and power off again.
+    ///
+    /// The sensor is shut down even if the read fails. Meant for nodes that
+    /// sleep between readings; the white channel is read only on request.
+    pub fn measure_once(&mut self, read_white: bool) -> Result<Measurement, LightSensorError<I2C::Error>> {
+        self.dev.set_shutdown(i2c::Shutdown::PowerOn)?;
+        let resolution = self.resolution()?;
+        self.dev.delay_ms(resolution.integration_time().as_ms());
+
+        let reading = self.read_channels(read_white);
+        let shutdown = self.dev.set_shutdown(i2c::Shutdown::PowerOff);
+        self.mark_settings_changed();
+        let (als_raw, white_raw) = reading?;
+        shutdown?;
+
+        Ok(Measurement::new(&resolution, als_raw, white_raw, false))
+    }
+
+    /// Set the count limits used by [`get_ambient_light_lux_auto`](Self::get_ambient_light_lux_auto).
+    pub fn set_auto_range(&mut self, auto_range: AutoRange) {
+        self.auto_range = auto_range;
+    }
+
+    /// Read lux, first moving gain and integration time to the step of
+    /// [`range::STEPS`] that gives the best resolution without saturating.
+    ///
+    /// The chosen settings are left on the device, so the next call starts
+    /// from them and usually reads only once.
+    pub fn get_ambient_light_lux_auto(&mut self) -> Result<f32, LightSensorError<I2C::Error>> {
+        let gain = self.dev.get_gain()?;
+        let integration_time = self.dev.get_integration_time()?;
+
+        let power_saving = (self.dev.get_power_saving_mode()?, self.dev.get_power_saving_mode_enabled()?);
+
+        let mut step = AutoRange::step_for(gain, integration_time);
+        if range::STEPS[step] != (gain, integration_time) {
+            self.apply_range_step(step, power_saving)?;
+        }
+
+        let mut raw = self.dev.get_ambient_light_output()?;
+        for _ in 0..range::STEPS.len() {
+            match self.auto_range.next_step(step, raw) {
+                Some(next) => {
+                    step = next;
+                    self.apply_range_step(step, power_saving)?;
+                    raw = self.dev.get_ambient_light_output()?;
+                },
+                None => break,
+            }
+        }
+
+        let (gain, integration_time) = range::STEPS[step];
+        Ok(Resolution::<V>::for_variant(gain, integration_time).with_calibration(self.calibration).lux(raw))
+    }
+
+    /// Block for one [refresh time](Self::refresh_time_ms): a full
+    /// integration period plus the power-saving wait, after which a sample
+    /// under the current settings is ready.
+    pub fn wait_for_integration(&mut self) -> Result<(), LightSensorError<I2C::Error>> {
+        let refresh_time = self.refresh_time_ms()?;
+        self.dev.delay_ms(refresh_time);
+        self.settings_changed = false;
+
+        Ok(())
+    }
+
+    /// Move to a step of [`range::STEPS`] and wait one refresh time, so the
+    /// next sample is taken at the new scale even in power-saving mode.
+    fn apply_range_step(&mut self, step: usize, (mode, enable): (i2c::PowerSavingMode, i2c::PowerSavingModeEnable)) -> Result<(), LightSensorError<I2C::Error>> {
+        let (gain, integration_time) = range::STEPS[step];
+        debug!("auto range step {}", step);
+
+        self.dev.set_scale(gain, integration_time)?;
+        self.mark_settings_changed();
+        self.refresh_threshold_window()?;
+        self.dev.delay_ms(i2c::refresh_time_ms(integration_time, mode, enable));
+        self.settings_changed = false;
+
+        Ok(())
+    }
+
+    /// Note a settings change: the next sample may be stale, and a
+    /// measurement being polled no longer matches the settings.
+    fn mark_settings_changed(&mut self) {
+        self.settings_changed = true;
+        self.pending_measurement = None;
+    }
+
+    fn read_channels(&mut self, read_white: bool) -> Result<(u16, Option<u16>), LightSensorError<I2C::Error>> {
+        let als_raw = self.dev.get_ambient_light_output()?;
+        let white_raw = if read_white { Some(self.dev.get_white_light_output()?) } else { None };
+
+        Ok((als_raw, white_raw))
+    }
+
+    /// Rewrite the raw thresholds of a lux window for the current scale.
+    ///
+    /// A limit beyond the new full scale is clamped to the top count.
+    fn refresh_threshold_window(&mut self) -> Result<(), LightSensorError<I2C::Error>> {
+        let Some((low, high)) = self.threshold_window else {
+            return Ok(());
+        };
+
+        let resolution = self.resolution()?;
+        let low_raw = resolution.raw_for_lux(low).unwrap_or(u16::MAX);
+        let high_raw = resolution.raw_for_lux(high).unwrap_or(u16::MAX);
+        debug!("threshold window {} {} counts", low_raw, high_raw);
+
+        self.dev.set_high_threshold_window(high_raw)?;
+        self.dev.set_low_threshold_window(low_raw)?;
+
+        Ok(())
+    }
+
 }
 
 
-// fn get_lux_raw_conversion_factor(it: i2c::IntegrationTime, gain: i2c::Gain) -> f32 {
-//     let gain_factor = match gain {
-//         i2c::Gain::X2 => 1.0,
-//         i2c::Gain::X1 => 2.0,
-//         i2c::Gain::X1_4 => 8.0,
-//         i2c::Gain::X1_8 => 16.0,
-//     };
-//     let it_factor = match it {
-//         i2c::IntegrationTime::Ms800 => 0.0036,
-//         i2c::IntegrationTime::Ms400 => 0.0072,
-//         i2c::IntegrationTime::Ms200 => 0.0144,
-//         i2c::IntegrationTime::Ms100 => 0.0288,
-//         i2c::IntegrationTime::Ms50 => 0.0576,
-//         i2c::IntegrationTime::Ms25 => 0.1152,
-//     };
-//     gain_factor * it_factor
-// }
-
-
-// pub fn compensate_high_lux(lux: f64) -> f64 {
-//     C3 * lux.powi(4)
-//         - C2 * lux.powi(3)
-//         + C1 * lux.powi(2)
-//         + C0 * lux
-// }
+/// Sensors found by [`scan`], one slot per standard address.
+pub type ScanResult = [Option<(Address, i2c::DeviceId)>; 2];
+
+/// Read the ID register at both standard addresses.
+///
+/// Slots follow `[Address::Default, Address::Alternative]`; an address
+/// nobody acknowledges is left empty. Compare [`DeviceId::device`](i2c::DeviceId::device)
+/// with [`ChipVariant::DEVICE_ID`] to tell the parts apart.
+pub fn scan<I2C: I2c>(i2c: &mut I2C) -> Result<ScanResult, LightSensorError<I2C::Error>> {
+    let mut found = [None; 2];
+    for (slot, address) in found.iter_mut().zip([Address::Default, Address::Alternative]) {
+        let mut buffer = [0u8; 2];
+        match i2c.write_read(address.into(), &[constants::registers::ID_REG], &mut buffer).map_err(i2c::LightSensorI2cError::from_bus) {
+            Ok(()) => *slot = Some((address, i2c::DeviceId::from_word(u16::from_le_bytes(buffer)))),
+            Err(i2c::LightSensorI2cError::NotPresent(_)) => (),
+            Err(error) => return Err(error.into()),
+        }
+    }
+
+    Ok(found)
+}
 
 
 #[cfg(test)]
@@ -152,24 +601,540 @@ mod tests {
     use i2c::constants;
 
     // Public imports
+    use embedded_hal::i2c::NoAcknowledgeSource;
     use embedded_hal_mock::eh1::{delay::NoopDelay, i2c::{Mock as I2cMock, Transaction as I2cTransaction}};
+    use embedded_hal_mock::eh1::delay::{CheckedDelay, Transaction as DelayTransaction};
+    use embedded_hal_mock::eh1::digital::{Mock as PinMock, State as PinState, Transaction as PinTransaction};
 
 
     #[test]
     fn start_light_sensor() {
         let address: u8 = constants::addresses::DEFAULT;
         let expectations = [
-            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x00]),
-            I2cTransaction::write(address, vec![constants::registers::SETTING_REG, 0x00, 0x00]),
-            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x00]),
+            I2cTransaction::write(address, vec![constants::registers::H_THRESH_REG, 0x00, 0x00]),
+            I2cTransaction::write(address, vec![constants::registers::L_THRESH_REG, 0x00, 0x00]),
+            I2cTransaction::write(address, vec![constants::registers::POWER_SAVE_REG, 0x00, 0x00]),
+            I2cTransaction::write(address, vec![constants::registers::SETTING_REG, 0x00, 0x1A]),
+        ];
+        let i2c = I2cMock::new(&expectations);
+        let mut i2c_clone = i2c.clone();
+
+        let _ = LightSensor::build(i2c, Address::Default, NoopDelay::new()) ;
+
+        i2c_clone.done();
+    }
+
+    #[test]
+    fn address_nack_is_not_present() {
+        let address: u8 = constants::addresses::DEFAULT;
+        let nack = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address);
+        let expectations = [
+            I2cTransaction::write_read(address, vec![constants::registers::AMBIENT_LIGHT_DATA_REG], vec![0x00, 0x00])
+                .with_error(nack),
+        ];
+        let i2c = I2cMock::new(&expectations);
+        let mut i2c_clone = i2c.clone();
+
+        let mut sensor = LightSensor::new(i2c, Address::Default, NoopDelay::new());
+        let error = sensor.get_ambient_light_lux().unwrap_err();
+        assert_eq!(error, LightSensorError::NotPresent(nack));
+        assert_eq!(error.kind(), Some(nack));
+
+        i2c_clone.done();
+    }
+
+    #[test]
+    fn custom_address() {
+        assert_eq!(Address::try_from(0x48), Ok(Address::Default));
+        assert_eq!(Address::try_from(0x10), Ok(Address::Alternative));
+        assert_eq!(Address::try_from(0x2A), Ok(Address::Custom(0x2A)));
+        assert_eq!(Address::try_from(0x80), Err(AddressError { address: 0x80 }));
+
+        let expectations = [
+            I2cTransaction::write_read(0x2A, vec![constants::registers::WHITE_LIGHT_DATA_REG], vec![0x10, 0x00]),
+        ];
+        let i2c = I2cMock::new(&expectations);
+        let mut i2c_clone = i2c.clone();
+
+        let mut sensor = LightSensor::new(i2c, Address::try_from(0x2A).unwrap(), NoopDelay::new());
+        assert_eq!(sensor.address(), 0x2A);
+        assert_eq!(sensor.get_white_light().unwrap(), 16.0);
+
+        i2c_clone.done();
+    }
+
+    #[test]
+    fn power_off_and_destroy() {
+        let address: u8 = constants::addresses::DEFAULT;
+        let expectations = [
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x10]),
+            I2cTransaction::write(address, vec![constants::registers::SETTING_REG, 0x01, 0x10]),
+            I2cTransaction::write_read(address, vec![constants::registers::ID_REG], vec![0x81, 0xD4]),
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x10])
+                .with_error(ErrorKind::ArbitrationLoss),
+        ];
+        let i2c = I2cMock::new(&expectations);
+
+        let sensor = LightSensor::new(i2c, Address::Default, NoopDelay::new());
+        let Ok((i2c, _delay)) = sensor.destroy_powered_off() else { panic!("shutdown failed") };
+
+        // The bus is usable again without the driver.
+        let mut sensor = LightSensor::new(i2c, Address::Default, NoopDelay::new());
+        sensor.identify().unwrap();
+
+        // A failed shutdown hands the driver back.
+        let Err((sensor, error)) = sensor.destroy_powered_off() else { panic!("shutdown succeeded") };
+        assert_eq!(error, LightSensorError::Bus(ErrorKind::ArbitrationLoss));
+        let (mut i2c, _delay) = sensor.destroy();
+        i2c.done();
+    }
+
+    #[test]
+    fn measurement_is_stale_until_integrated() {
+        let address: u8 = constants::addresses::DEFAULT;
+        let expectations = [
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x10]),
             I2cTransaction::write(address, vec![constants::registers::SETTING_REG, 0x00, 0x18]),
             I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x18]),
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x18]),
+            I2cTransaction::write_read(address, vec![constants::registers::AMBIENT_LIGHT_DATA_REG], vec![0xE8, 0x03]),
+            I2cTransaction::write_read(address, vec![constants::registers::WHITE_LIGHT_DATA_REG], vec![0xD0, 0x07]),
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x18]),
+            I2cTransaction::write_read(address, vec![constants::registers::POWER_SAVE_REG], vec![0x00, 0x00]),
+            I2cTransaction::write_read(address, vec![constants::registers::POWER_SAVE_REG], vec![0x00, 0x00]),
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x18]),
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x18]),
+            I2cTransaction::write_read(address, vec![constants::registers::AMBIENT_LIGHT_DATA_REG], vec![0xFF, 0xFF]),
+            I2cTransaction::write_read(address, vec![constants::registers::WHITE_LIGHT_DATA_REG], vec![0xFF, 0xFF]),
+        ];
+        let i2c = I2cMock::new(&expectations);
+        let mut i2c_clone = i2c.clone();
+
+        let mut sensor = LightSensor::new(i2c, Address::Default, NoopDelay::new());
+        sensor.set_gain(i2c::Gain::X1_4).unwrap();
+        let measurement = sensor.measure().unwrap();
+        assert!(measurement.possibly_stale && !measurement.is_valid());
+
+        sensor.wait_for_integration().unwrap();
+        let measurement = sensor.measure().unwrap();
+        assert_eq!(measurement.gain, i2c::Gain::X1_4);
+        assert!(measurement.saturated && measurement.corrected && !measurement.possibly_stale);
+        assert!(!measurement.is_valid());
+
+        i2c_clone.done();
+    }
+
+    #[test]
+    fn read_fresh_waits_for_refresh() {
+        let address: u8 = constants::addresses::DEFAULT;
+        let expectations = [
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x1A]),
+            I2cTransaction::write_read(address, vec![constants::registers::POWER_SAVE_REG], vec![0x01, 0x00]),
+            I2cTransaction::write_read(address, vec![constants::registers::AMBIENT_LIGHT_DATA_REG], vec![0xE8, 0x03]),
+            I2cTransaction::write_read(address, vec![constants::registers::WHITE_LIGHT_DATA_REG], vec![0xD0, 0x07]),
+            I2cTransaction::write_read(address, vec![constants::registers::AMBIENT_LIGHT_DATA_REG], vec![0xE8, 0x03]),
+            I2cTransaction::write_read(address, vec![constants::registers::WHITE_LIGHT_DATA_REG], vec![0xD0, 0x07]),
+        ];
+        let i2c = I2cMock::new(&expectations);
+        let mut i2c_clone = i2c.clone();
+        // 50 ms integration plus the 500 ms wait of power-saving mode 1.
+        let delay = CheckedDelay::new(&[DelayTransaction::delay_ms(550)]);
+        let mut delay_clone = delay.clone();
+
+        let mut sensor = LightSensor::new(i2c, Address::Default, delay);
+        sensor.sync_from_device().unwrap();
+        assert!(!sensor.read_fresh().unwrap().possibly_stale);
+        assert_eq!(sensor.read_fresh().unwrap().white_raw, Some(2000));
+
+        i2c_clone.done();
+        delay_clone.done();
+    }
+
+    #[test]
+    fn wait_for_integration_covers_power_saving() {
+        let address: u8 = constants::addresses::DEFAULT;
+        let expectations = [
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x1A]),
+            I2cTransaction::write_read(address, vec![constants::registers::POWER_SAVE_REG], vec![0x07, 0x00]),
+            I2cTransaction::write(address, vec![constants::registers::SETTING_REG, 0x00, 0x12]),
+            I2cTransaction::write_read(address, vec![constants::registers::AMBIENT_LIGHT_DATA_REG], vec![0xE8, 0x03]),
+            I2cTransaction::write_read(address, vec![constants::registers::WHITE_LIGHT_DATA_REG], vec![0xD0, 0x07]),
+        ];
+        let i2c = I2cMock::new(&expectations);
+        let mut i2c_clone = i2c.clone();
+        // 50 ms integration plus the 4000 ms wait of power-saving mode 4.
+        let delay = CheckedDelay::new(&[DelayTransaction::delay_ms(4050)]);
+        let mut delay_clone = delay.clone();
+
+        let mut sensor = LightSensor::new(i2c, Address::Default, delay);
+        sensor.sync_from_device().unwrap();
+        sensor.set_gain(i2c::Gain::X1_8).unwrap();
+        sensor.wait_for_integration().unwrap();
+        let measurement = sensor.read_fresh().unwrap();
+        assert_eq!(measurement.gain, i2c::Gain::X1_8);
+        assert!(!measurement.possibly_stale);
+
+        i2c_clone.done();
+        delay_clone.done();
+    }
+
+    #[test]
+    fn measure_once_powers_down() {
+        let address: u8 = constants::addresses::DEFAULT;
+        let expectations = [
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x01, 0x1A]),
+            I2cTransaction::write_read(address, vec![constants::registers::POWER_SAVE_REG], vec![0x00, 0x00]),
             I2cTransaction::write(address, vec![constants::registers::SETTING_REG, 0x00, 0x1A]),
+            I2cTransaction::write_read(address, vec![constants::registers::AMBIENT_LIGHT_DATA_REG], vec![0xE8, 0x03]),
+            I2cTransaction::write(address, vec![constants::registers::SETTING_REG, 0x01, 0x1A]),
         ];
         let i2c = I2cMock::new(&expectations);
         let mut i2c_clone = i2c.clone();
+        let delay = CheckedDelay::new(&[DelayTransaction::delay_ms(4), DelayTransaction::delay_ms(50)]);
+        let mut delay_clone = delay.clone();
 
-        let _ = LightSensor::build(i2c, Address::Default, NoopDelay::new()) ;
+        let mut sensor = LightSensor::new(i2c, Address::Default, delay);
+        sensor.sync_from_device().unwrap();
+        let measurement = sensor.measure_once(false).unwrap();
+        assert_eq!(measurement.als_raw, 1000);
+        assert_eq!(measurement.white_raw, None);
+        assert!(!measurement.possibly_stale);
+
+        i2c_clone.done();
+        delay_clone.done();
+    }
+
+    #[test]
+    fn poll_measurement_without_blocking() {
+        let address: u8 = constants::addresses::DEFAULT;
+        let expectations = [
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x1A]),
+            I2cTransaction::write_read(address, vec![constants::registers::POWER_SAVE_REG], vec![0x00, 0x00]),
+            I2cTransaction::write_read(address, vec![constants::registers::AMBIENT_LIGHT_DATA_REG], vec![0xE8, 0x03]),
+            I2cTransaction::write_read(address, vec![constants::registers::WHITE_LIGHT_DATA_REG], vec![0xD0, 0x07]),
+        ];
+        let i2c = I2cMock::new(&expectations);
+        let mut i2c_clone = i2c.clone();
+
+        let mut sensor = LightSensor::new(i2c, Address::Default, NoopDelay::new());
+        assert_eq!(sensor.poll_measurement(0), Err(nb::Error::Other(LightSensorError::NotStarted)));
+        sensor.sync_from_device().unwrap();
+        sensor.start_measurement(u32::MAX - 10).unwrap();
+        assert_eq!(sensor.poll_measurement(20), Err(nb::Error::WouldBlock));
+        let measurement = sensor.poll_measurement(39).unwrap();
+        assert_eq!(measurement.als_raw, 1000);
+        assert!(!measurement.possibly_stale);
+
+        i2c_clone.done();
+    }
+
+    #[test]
+    fn settings_change_drops_polled_measurement() {
+        let address: u8 = constants::addresses::DEFAULT;
+        let expectations = [
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x1A]),
+            I2cTransaction::write_read(address, vec![constants::registers::POWER_SAVE_REG], vec![0x00, 0x00]),
+            I2cTransaction::write(address, vec![constants::registers::SETTING_REG, 0x00, 0x12]),
+            I2cTransaction::write_read(address, vec![constants::registers::AMBIENT_LIGHT_DATA_REG], vec![0xE8, 0x03]),
+            I2cTransaction::write_read(address, vec![constants::registers::WHITE_LIGHT_DATA_REG], vec![0xD0, 0x07]),
+        ];
+        let i2c = I2cMock::new(&expectations);
+        let mut i2c_clone = i2c.clone();
+
+        let mut sensor = LightSensor::new(i2c, Address::Default, NoopDelay::new());
+        sensor.sync_from_device().unwrap();
+        sensor.start_measurement(0).unwrap();
+        sensor.set_gain(i2c::Gain::X1_8).unwrap();
+        assert_eq!(sensor.poll_measurement(50), Err(nb::Error::Other(LightSensorError::NotStarted)));
+
+        sensor.start_measurement(50).unwrap();
+        let measurement = sensor.poll_measurement(100).unwrap();
+        assert_eq!(measurement.gain, i2c::Gain::X1_8);
+        assert!(!measurement.possibly_stale);
+
+        i2c_clone.done();
+    }
+
+    #[test]
+    fn median_of_readings() {
+        let address: u8 = constants::addresses::DEFAULT;
+        let expectations = [
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x1A]),
+            I2cTransaction::write_read(address, vec![constants::registers::POWER_SAVE_REG], vec![0x00, 0x00]),
+            I2cTransaction::write_read(address, vec![constants::registers::AMBIENT_LIGHT_DATA_REG], vec![0x64, 0x00]),
+            I2cTransaction::write_read(address, vec![constants::registers::AMBIENT_LIGHT_DATA_REG], vec![0xFF, 0x0F]),
+            I2cTransaction::write_read(address, vec![constants::registers::AMBIENT_LIGHT_DATA_REG], vec![0x68, 0x00]),
+        ];
+        let i2c = I2cMock::new(&expectations);
+        let mut i2c_clone = i2c.clone();
+        let delay = CheckedDelay::new(&[DelayTransaction::delay_ms(50), DelayTransaction::delay_ms(50), DelayTransaction::delay_ms(50)]);
+        let mut delay_clone = delay.clone();
+
+        let mut sensor = LightSensor::new(i2c, Address::Default, delay);
+        sensor.sync_from_device().unwrap();
+        let median = sensor.read_aggregated::<3>(Strategy::Median).unwrap();
+        let resolution = Resolution::new(i2c::Gain::X1_4, i2c::IntegrationTime::Ms50);
+        assert_eq!(median.value, resolution.lux(0x68));
+        assert_eq!(median.spread(), resolution.lux(0x0FFF) - resolution.lux(0x64));
+
+        i2c_clone.done();
+        delay_clone.done();
+    }
+
+    #[test]
+    fn probe_checks_device_id() {
+        let expectations = [
+            I2cTransaction::write_read(constants::addresses::DEFAULT, vec![constants::registers::ID_REG], vec![0x81, 0xD4]),
+            I2cTransaction::write_read(constants::addresses::DEFAULT, vec![constants::registers::ID_REG], vec![0x35, 0xD4]),
+        ];
+        let i2c = I2cMock::new(&expectations);
+        let mut i2c_clone = i2c.clone();
+
+        let mut sensor = LightSensor::new(i2c, Address::Default, NoopDelay::new());
+        assert_eq!(sensor.probe().unwrap(), i2c::DeviceId { device: 0x81, address_option: 0xD4 });
+        assert_eq!(sensor.probe().unwrap_err(), LightSensorError::WrongDevice { id: 0x35 });
+
+        i2c_clone.done();
+    }
+
+    #[test]
+    fn scan_skips_silent_addresses() {
+        let nack = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address);
+        let expectations = [
+            I2cTransaction::write_read(constants::addresses::DEFAULT, vec![constants::registers::ID_REG], vec![0x00, 0x00])
+                .with_error(nack),
+            I2cTransaction::write_read(constants::addresses::ALTERNATIVE, vec![constants::registers::ID_REG], vec![0x81, 0xC4]),
+        ];
+        let mut i2c = I2cMock::new(&expectations);
+
+        let found = scan(&mut i2c).unwrap();
+        assert_eq!(found, [None, Some((Address::Alternative, i2c::DeviceId { device: 0x81, address_option: 0xC4 }))]);
+
+        i2c.done();
+    }
+
+    #[test]
+    fn invalid_integration_time_is_reported() {
+        let address: u8 = constants::addresses::DEFAULT;
+        let expectations = [
+            I2cTransaction::write_read(address, vec![constants::registers::AMBIENT_LIGHT_DATA_REG], vec![0x10, 0x00]),
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x01]),
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x01]),
+        ];
+        let i2c = I2cMock::new(&expectations);
+        let mut i2c_clone = i2c.clone();
+
+        let mut sensor = LightSensor::new(i2c, Address::Default, NoopDelay::new());
+        let error = sensor.get_ambient_light_lux().unwrap_err();
+        assert_eq!(error, LightSensorError::InvalidValue { register: constants::registers::SETTING_REG, value: 0x0100 });
+        assert_eq!(error.kind(), None);
+
+        i2c_clone.done();
+    }
+
+    #[test]
+    fn auto_range_raises_sensitivity() {
+        let address: u8 = constants::addresses::DEFAULT;
+        let setting = constants::registers::SETTING_REG;
+        let expectations = [
+            I2cTransaction::write_read(address, vec![setting], vec![0x00, 0x1A]),
+            I2cTransaction::write_read(address, vec![setting], vec![0x00, 0x1A]),
+            I2cTransaction::write_read(address, vec![constants::registers::POWER_SAVE_REG], vec![0x00, 0x00]),
+            I2cTransaction::write_read(address, vec![constants::registers::POWER_SAVE_REG], vec![0x00, 0x00]),
+            // Move to 1/8 gain, 100 ms
+            I2cTransaction::write_read(address, vec![setting], vec![0x00, 0x1A]),
+            I2cTransaction::write(address, vec![setting, 0x00, 0x10]),
+            I2cTransaction::write_read(address, vec![constants::registers::AMBIENT_LIGHT_DATA_REG], vec![0x32, 0x00]),
+            // Too dark, move to 1/4 gain, 100 ms
+            I2cTransaction::write_read(address, vec![setting], vec![0x00, 0x10]),
+            I2cTransaction::write(address, vec![setting, 0x00, 0x18]),
+            I2cTransaction::write_read(address, vec![constants::registers::AMBIENT_LIGHT_DATA_REG], vec![0xC8, 0x00]),
+        ];
+        let i2c = I2cMock::new(&expectations);
+        let mut i2c_clone = i2c.clone();
+
+        let mut sensor = LightSensor::new(i2c, Address::Default, NoopDelay::new());
+        let lux = sensor.get_ambient_light_lux_auto().unwrap();
+        assert_eq!(lux, Resolution::new(i2c::Gain::X1_4, i2c::IntegrationTime::Ms100).lux(200));
+
+        i2c_clone.done();
+    }
+
+    #[test]
+    fn auto_range_waits_for_power_saving() {
+        let address: u8 = constants::addresses::DEFAULT;
+        let setting = constants::registers::SETTING_REG;
+        let expectations = [
+            I2cTransaction::write_read(address, vec![setting], vec![0x00, 0x10]),
+            I2cTransaction::write_read(address, vec![constants::registers::POWER_SAVE_REG], vec![0x03, 0x00]),
+            I2cTransaction::write_read(address, vec![constants::registers::AMBIENT_LIGHT_DATA_REG], vec![0x32, 0x00]),
+            // Too dark, move to 1/4 gain, 100 ms
+            I2cTransaction::write(address, vec![setting, 0x00, 0x18]),
+            I2cTransaction::write_read(address, vec![constants::registers::AMBIENT_LIGHT_DATA_REG], vec![0xC8, 0x00]),
+        ];
+        let i2c = I2cMock::new(&expectations);
+        let mut i2c_clone = i2c.clone();
+        // 100 ms integration plus the 1000 ms wait of power-saving mode 2.
+        let delay = CheckedDelay::new(&[DelayTransaction::delay_ms(1100)]);
+        let mut delay_clone = delay.clone();
+
+        let mut sensor = LightSensor::new(i2c, Address::Default, delay);
+        sensor.sync_from_device().unwrap();
+        let lux = sensor.get_ambient_light_lux_auto().unwrap();
+        assert_eq!(lux, Resolution::new(i2c::Gain::X1_4, i2c::IntegrationTime::Ms100).lux(200));
+
+        i2c_clone.done();
+        delay_clone.done();
+    }
+
+    #[test]
+    fn shadow_registers_skip_reads() {
+        let address: u8 = constants::addresses::DEFAULT;
+        let expectations = [
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x1A]),
+            I2cTransaction::write_read(address, vec![constants::registers::POWER_SAVE_REG], vec![0x00, 0x00]),
+            I2cTransaction::write_read(address, vec![constants::registers::AMBIENT_LIGHT_DATA_REG], vec![0x10, 0x00]),
+            I2cTransaction::write(address, vec![constants::registers::SETTING_REG, 0x00, 0x02]),
+            I2cTransaction::write_read(address, vec![constants::registers::AMBIENT_LIGHT_DATA_REG], vec![0x10, 0x00]),
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x02]),
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x02]),
+        ];
+        let i2c = I2cMock::new(&expectations);
+        let mut i2c_clone = i2c.clone();
+
+        let mut sensor = LightSensor::new(i2c, Address::Default, NoopDelay::new());
+        sensor.sync_from_device().unwrap();
+        let lux = sensor.get_ambient_light_lux().unwrap();
+        assert_eq!(lux, Resolution::new(i2c::Gain::X1_4, i2c::IntegrationTime::Ms50).lux(16));
+        sensor.dev.set_gain(i2c::Gain::X1).unwrap();
+        let lux = sensor.get_ambient_light_lux().unwrap();
+        assert_eq!(lux, Resolution::new(i2c::Gain::X1, i2c::IntegrationTime::Ms50).lux(16));
+
+        sensor.invalidate();
+        sensor.convert_raw_to_lux(16).unwrap();
+
+        i2c_clone.done();
+    }
+
+    #[test]
+    fn apply_and_read_config() {
+        let address: u8 = constants::addresses::DEFAULT;
+        let expectations = [
+            I2cTransaction::write(address, vec![constants::registers::H_THRESH_REG, 0xA0, 0x0F]),
+            I2cTransaction::write(address, vec![constants::registers::L_THRESH_REG, 0xC8, 0x00]),
+            I2cTransaction::write(address, vec![constants::registers::POWER_SAVE_REG, 0x05, 0x00]),
+            I2cTransaction::write(address, vec![constants::registers::SETTING_REG, 0xE2, 0x08]),
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0xE2, 0x08]),
+            I2cTransaction::write_read(address, vec![constants::registers::POWER_SAVE_REG], vec![0x05, 0x00]),
+            I2cTransaction::write_read(address, vec![constants::registers::H_THRESH_REG], vec![0xA0, 0x0F]),
+            I2cTransaction::write_read(address, vec![constants::registers::L_THRESH_REG], vec![0xC8, 0x00]),
+        ];
+        let i2c = I2cMock::new(&expectations);
+        let mut i2c_clone = i2c.clone();
+
+        let config = LightSensor::builder()
+            .gain(i2c::Gain::X2)
+            .integration_time(i2c::IntegrationTime::Ms800)
+            .persist_protect_number(i2c::PersistenceProtectNumber::N4)
+            .interrupt_enable(i2c::InterruptEnable::Enable)
+            .power_saving_mode(i2c::PowerSavingMode::M3)
+            .power_saving_mode_enable(i2c::PowerSavingModeEnable::Enable)
+            .high_threshold(4000)
+            .low_threshold(200)
+            .build();
+        let mut sensor = LightSensor::new(i2c, Address::Default, NoopDelay::new());
+        sensor.apply_config(&config).unwrap();
+        assert_eq!(sensor.read_config().unwrap(), config);
+
+        i2c_clone.done();
+    }
+
+    #[test]
+    fn threshold_window_follows_gain() {
+        let address: u8 = constants::addresses::DEFAULT;
+        let expectations = [
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x1A]),
+            I2cTransaction::write_read(address, vec![constants::registers::POWER_SAVE_REG], vec![0x00, 0x00]),
+            I2cTransaction::write(address, vec![constants::registers::H_THRESH_REG, 0x58, 0x03]),
+            I2cTransaction::write(address, vec![constants::registers::L_THRESH_REG, 0x56, 0x00]),
+            I2cTransaction::write(address, vec![constants::registers::SETTING_REG, 0x00, 0x02]),
+            I2cTransaction::write(address, vec![constants::registers::H_THRESH_REG, 0x5F, 0x0D]),
+            I2cTransaction::write(address, vec![constants::registers::L_THRESH_REG, 0x57, 0x01]),
+        ];
+        let i2c = I2cMock::new(&expectations);
+        let mut i2c_clone = i2c.clone();
+
+        let mut sensor = LightSensor::new(i2c, Address::Default, NoopDelay::new());
+        sensor.sync_from_device().unwrap();
+        sensor.set_threshold_window_lux(46.0, 460.0).unwrap();
+        sensor.set_gain(i2c::Gain::X1).unwrap();
+        assert_eq!(sensor.get_threshold_window_lux(), Some((46.0, 460.0)));
+
+        assert!(matches!(sensor.set_threshold_window_lux(46.0, 1.0e6), Err(LightSensorError::ThresholdOutOfRange)));
+        assert!(matches!(sensor.set_threshold_window_lux(-1.0, 10.0), Err(LightSensorError::ThresholdOutOfRange)));
+
+        i2c_clone.done();
+    }
+
+    #[test]
+    fn threshold_event_on_interrupt_pin() {
+        let address: u8 = constants::addresses::DEFAULT;
+        let expectations = [
+            I2cTransaction::write_read(address, vec![constants::registers::INTERRUPT_REG], vec![0x00, 0x40]),
+            I2cTransaction::write_read(address, vec![constants::registers::AMBIENT_LIGHT_DATA_REG], vec![0xE8, 0x03]),
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x1A]),
+            I2cTransaction::write_read(address, vec![constants::registers::SETTING_REG], vec![0x00, 0x1A]),
+        ];
+        let i2c = I2cMock::new(&expectations);
+        let mut i2c_clone = i2c.clone();
+        let pin_expectations = [
+            PinTransaction::get(PinState::High),
+            PinTransaction::get(PinState::High),
+            PinTransaction::get(PinState::Low),
+        ];
+        let mut pin = PinMock::new(&pin_expectations);
+
+        let mut sensor = LightSensor::new(i2c, Address::Default, NoopDelay::new());
+        let event = sensor.wait_for_threshold_event(&mut pin).unwrap();
+        assert_eq!(event, ThresholdEvent {
+            high: true,
+            low: false,
+            raw: 1000,
+            lux: Resolution::new(i2c::Gain::X1_4, i2c::IntegrationTime::Ms50).lux(1000),
+        });
+
+        i2c_clone.done();
+        pin.done();
+    }
+
+    #[test]
+    fn interrupt_status_from_one_read() {
+        let address: u8 = constants::addresses::DEFAULT;
+        let expectations = [
+            I2cTransaction::write_read(address, vec![constants::registers::INTERRUPT_REG], vec![0x00, 0xC0]),
+        ];
+        let i2c = I2cMock::new(&expectations);
+        let mut i2c_clone = i2c.clone();
+
+        let mut sensor = LightSensor::new(i2c, Address::Default, NoopDelay::new());
+        let status = sensor.read_interrupt_status().unwrap();
+        assert_eq!(status, i2c::InterruptStatus { low: i2c::Threshold::Exceeded, high: i2c::Threshold::Exceeded });
+
+        i2c_clone.done();
+    }
+
+    #[test]
+    fn bus_error_is_propagated() {
+        let address: u8 = constants::addresses::DEFAULT;
+        let expectations = [
+            I2cTransaction::write(address, vec![constants::registers::H_THRESH_REG, 0x00, 0x00])
+                .with_error(ErrorKind::ArbitrationLoss),
+        ];
+        let i2c = I2cMock::new(&expectations);
+        let mut i2c_clone = i2c.clone();
+
+        let result = LightSensor::build(i2c, Address::Default, NoopDelay::new());
+        assert!(matches!(result, Err(LightSensorError::Bus(ErrorKind::ArbitrationLoss))));
 
         i2c_clone.done();
     }
@@ -0,0 +1,80 @@
+//! Readings together with the settings they were taken at.
+
+// Local imports
+use crate::i2c::{Gain, IntegrationTime};
+use crate::resolution::Resolution;
+use crate::variant::ChipVariant;
+
+
+/// Counts below which a reading is dominated by noise, following Vishay's
+/// "Designing the VEML7700 Into an Application".
+pub const NOISE_FLOOR: u16 = 100;
+
+
+/// One ambient light reading.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Measurement {
+    /// Ambient light counts.
+    pub als_raw: u16,
+    /// White channel counts, if they were read.
+    pub white_raw: Option<u16>,
+    /// `als_raw` converted to calibrated lux.
+    pub lux: f32,
+    pub gain: Gain,
+    pub integration_time: IntegrationTime,
+    /// The non-linearity correction was applied to `lux`.
+    pub corrected: bool,
+    /// The ambient light channel sat at its top count, so `lux` is a lower bound.
+    pub saturated: bool,
+    /// Fewer than [`NOISE_FLOOR`] counts were read.
+    pub below_noise_floor: bool,
+    /// The settings changed without a full refresh time since, so
+    /// the counts may belong to the previous settings.
+    pub possibly_stale: bool,
+}
+
+impl Measurement {
+    /// Describe counts read at `resolution`.
+    pub fn new<V: ChipVariant>(resolution: &Resolution<V>, als_raw: u16, white_raw: Option<u16>, possibly_stale: bool) -> Self {
+        Measurement {
+            als_raw,
+            white_raw,
+            lux: resolution.lux(als_raw),
+            gain: resolution.gain(),
+            integration_time: resolution.integration_time(),
+            corrected: resolution.is_corrected(als_raw),
+            saturated: als_raw == u16::MAX,
+            below_noise_floor: als_raw < NOISE_FLOOR,
+            possibly_stale,
+        }
+    }
+
+    /// No flag casts doubt on the reading.
+    pub fn is_valid(&self) -> bool {
+        !(self.saturated || self.below_noise_floor || self.possibly_stale)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags() {
+        let resolution = Resolution::new(Gain::X1_8, IntegrationTime::Ms25);
+
+        let clipped = Measurement::new(&resolution, u16::MAX, None, false);
+        assert!(clipped.saturated && clipped.corrected && !clipped.is_valid());
+
+        let dark = Measurement::new(&resolution, 20, Some(25), false);
+        assert!(dark.below_noise_floor && !dark.corrected);
+        assert_eq!(dark.white_raw, Some(25));
+
+        let fine = Measurement::new(&resolution, 400, None, false);
+        assert!(fine.is_valid());
+        assert_eq!(fine.lux, resolution.lux(400));
+    }
+}
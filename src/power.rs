@@ -0,0 +1,166 @@
+//! Refresh time and average current of the power-saving modes, from the
+//! datasheet's table.
+
+// Local imports
+use crate::i2c::{refresh_time_ms, IntegrationTime, PowerSavingMode, PowerSavingModeEnable};
+
+
+/// Typical supply current while integrating continuously.
+pub const ACTIVE_CURRENT_UA: f32 = 45.0;
+
+/// Settings the planner chooses from, with their typical average current:
+/// the datasheet's power-saving table, then continuous operation at every
+/// integration time.
+const CANDIDATES: [(IntegrationTime, Option<PowerSavingMode>, f32); 22] = [
+    (IntegrationTime::Ms100, Some(PowerSavingMode::M1), 8.0),
+    (IntegrationTime::Ms100, Some(PowerSavingMode::M2), 5.0),
+    (IntegrationTime::Ms100, Some(PowerSavingMode::M3), 3.0),
+    (IntegrationTime::Ms100, Some(PowerSavingMode::M4), 2.0),
+    (IntegrationTime::Ms200, Some(PowerSavingMode::M1), 13.0),
+    (IntegrationTime::Ms200, Some(PowerSavingMode::M2), 8.0),
+    (IntegrationTime::Ms200, Some(PowerSavingMode::M3), 5.0),
+    (IntegrationTime::Ms200, Some(PowerSavingMode::M4), 3.0),
+    (IntegrationTime::Ms400, Some(PowerSavingMode::M1), 20.0),
+    (IntegrationTime::Ms400, Some(PowerSavingMode::M2), 13.0),
+    (IntegrationTime::Ms400, Some(PowerSavingMode::M3), 8.0),
+    (IntegrationTime::Ms400, Some(PowerSavingMode::M4), 5.0),
+    (IntegrationTime::Ms800, Some(PowerSavingMode::M1), 25.0),
+    (IntegrationTime::Ms800, Some(PowerSavingMode::M2), 20.0),
+    (IntegrationTime::Ms800, Some(PowerSavingMode::M3), 13.0),
+    (IntegrationTime::Ms800, Some(PowerSavingMode::M4), 8.0),
+    (IntegrationTime::Ms25, None, ACTIVE_CURRENT_UA),
+    (IntegrationTime::Ms50, None, ACTIVE_CURRENT_UA),
+    (IntegrationTime::Ms100, None, ACTIVE_CURRENT_UA),
+    (IntegrationTime::Ms200, None, ACTIVE_CURRENT_UA),
+    (IntegrationTime::Ms400, None, ACTIVE_CURRENT_UA),
+    (IntegrationTime::Ms800, None, ACTIVE_CURRENT_UA),
+];
+
+
+/// Integration time and power-saving mode, with what they cost.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PowerPlan {
+    pub integration_time: IntegrationTime,
+    /// Power-saving mode, or `None` to integrate continuously.
+    pub mode: Option<PowerSavingMode>,
+    /// Time between two samples.
+    pub refresh_ms: u32,
+    /// Average supply current.
+    pub current_ua: f32,
+}
+
+impl PowerPlan {
+    /// Plan for a row of the datasheet's table, or continuous operation
+    /// with `mode` set to `None`. Power saving is not rated below 100 ms.
+    pub fn new(integration_time: IntegrationTime, mode: Option<PowerSavingMode>) -> Option<Self> {
+        CANDIDATES.iter()
+            .find(|&&(candidate_time, candidate_mode, _)| candidate_time == integration_time && candidate_mode == mode)
+            .map(|&(integration_time, mode, current_ua)| Self::from_row(integration_time, mode, current_ua))
+    }
+
+    /// Lowest-current plan that delivers a sample at least every `interval_ms`.
+    pub fn for_refresh_interval(interval_ms: u32) -> Option<Self> {
+        Self::best(|plan| plan.refresh_ms <= interval_ms, |plan| plan.current_ua)
+    }
+
+    /// Fastest-refreshing plan that draws at most `budget_ua` on average.
+    pub fn for_current_budget(budget_ua: f32) -> Option<Self> {
+        Self::best(|plan| plan.current_ua <= budget_ua, |plan| plan.refresh_ms as f32)
+    }
+
+    pub fn power_saving_mode_enable(&self) -> PowerSavingModeEnable {
+        match self.mode {
+            Some(_) => PowerSavingModeEnable::Enable,
+            None => PowerSavingModeEnable::Disable,
+        }
+    }
+
+    fn from_row(integration_time: IntegrationTime, mode: Option<PowerSavingMode>, current_ua: f32) -> Self {
+        let refresh_ms = match mode {
+            Some(mode) => refresh_time_ms(integration_time, mode, PowerSavingModeEnable::Enable),
+            None => integration_time.as_ms(),
+        };
+
+        PowerPlan { integration_time, mode, refresh_ms, current_ua }
+    }
+
+    /// Candidate meeting `accept` with the lowest `cost`; among equal costs
+    /// the longer integration time wins, for its finer resolution.
+    fn best(accept: impl Fn(&Self) -> bool, cost: impl Fn(&Self) -> f32) -> Option<Self> {
+        let mut best: Option<Self> = None;
+        for (integration_time, mode, current_ua) in CANDIDATES {
+            let plan = Self::from_row(integration_time, mode, current_ua);
+            if !accept(&plan) {
+                continue;
+            }
+            best = match best {
+                Some(current) if cost(&current) < cost(&plan) => Some(current),
+                Some(current) if cost(&current) == cost(&plan) && current.integration_time.as_ms() >= plan.integration_time.as_ms() => Some(current),
+                _ => Some(plan),
+            };
+        }
+        best
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_datasheet_table() {
+        let table = [
+            (IntegrationTime::Ms100, PowerSavingMode::M1, 600, 8.0),
+            (IntegrationTime::Ms100, PowerSavingMode::M2, 1100, 5.0),
+            (IntegrationTime::Ms100, PowerSavingMode::M3, 2100, 3.0),
+            (IntegrationTime::Ms100, PowerSavingMode::M4, 4100, 2.0),
+            (IntegrationTime::Ms200, PowerSavingMode::M1, 700, 13.0),
+            (IntegrationTime::Ms200, PowerSavingMode::M2, 1200, 8.0),
+            (IntegrationTime::Ms200, PowerSavingMode::M3, 2200, 5.0),
+            (IntegrationTime::Ms200, PowerSavingMode::M4, 4200, 3.0),
+            (IntegrationTime::Ms400, PowerSavingMode::M1, 900, 20.0),
+            (IntegrationTime::Ms400, PowerSavingMode::M2, 1400, 13.0),
+            (IntegrationTime::Ms400, PowerSavingMode::M3, 2400, 8.0),
+            (IntegrationTime::Ms400, PowerSavingMode::M4, 4400, 5.0),
+            (IntegrationTime::Ms800, PowerSavingMode::M1, 1300, 25.0),
+            (IntegrationTime::Ms800, PowerSavingMode::M2, 1800, 20.0),
+            (IntegrationTime::Ms800, PowerSavingMode::M3, 2800, 13.0),
+            (IntegrationTime::Ms800, PowerSavingMode::M4, 4800, 8.0),
+        ];
+        for (integration_time, mode, refresh_ms, current_ua) in table {
+            let plan = PowerPlan::new(integration_time, Some(mode)).unwrap();
+            assert_eq!((plan.refresh_ms, plan.current_ua), (refresh_ms, current_ua));
+        }
+
+        assert_eq!(PowerPlan::new(IntegrationTime::Ms50, None).unwrap().current_ua, ACTIVE_CURRENT_UA);
+        assert_eq!(PowerPlan::new(IntegrationTime::Ms50, Some(PowerSavingMode::M1)), None);
+    }
+
+    #[test]
+    fn plans_for_interval() {
+        let plan = PowerPlan::for_refresh_interval(1500).unwrap();
+        assert_eq!((plan.integration_time, plan.mode), (IntegrationTime::Ms100, Some(PowerSavingMode::M2)));
+
+        let plan = PowerPlan::for_refresh_interval(300).unwrap();
+        assert_eq!((plan.integration_time, plan.mode), (IntegrationTime::Ms200, None));
+
+        assert_eq!(PowerPlan::for_refresh_interval(10), None);
+    }
+
+    #[test]
+    fn plans_for_budget() {
+        let plan = PowerPlan::for_current_budget(10.0).unwrap();
+        assert_eq!((plan.integration_time, plan.mode), (IntegrationTime::Ms100, Some(PowerSavingMode::M1)));
+
+        let plan = PowerPlan::for_current_budget(4.8).unwrap();
+        assert_eq!((plan.integration_time, plan.mode), (IntegrationTime::Ms100, Some(PowerSavingMode::M3)));
+
+        let plan = PowerPlan::for_current_budget(100.0).unwrap();
+        assert_eq!((plan.integration_time, plan.mode), (IntegrationTime::Ms25, None));
+
+        assert_eq!(PowerPlan::for_current_budget(0.1), None);
+    }
+}
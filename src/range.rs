@@ -0,0 +1,123 @@
+//! Automatic gain and integration-time ranging.
+//!
+//! Follows the flow from Vishay's "Designing the VEML7700 Into an Application":
+//! start from a low sensitivity, raise the gain and then the integration time
+//! while the counts are too low to be meaningful, and back off towards shorter
+//! integration times when the counts approach saturation.
+
+// Local imports
+use crate::i2c::{Gain, IntegrationTime};
+use crate::resolution::resolution_factor;
+
+
+/// Settings the auto-ranging walks through, from least to most sensitive.
+pub const STEPS: [(Gain, IntegrationTime); 9] = [
+    (Gain::X1_8, IntegrationTime::Ms25),
+    (Gain::X1_8, IntegrationTime::Ms50),
+    (Gain::X1_8, IntegrationTime::Ms100),
+    (Gain::X1_4, IntegrationTime::Ms100),
+    (Gain::X1, IntegrationTime::Ms100),
+    (Gain::X2, IntegrationTime::Ms100),
+    (Gain::X2, IntegrationTime::Ms200),
+    (Gain::X2, IntegrationTime::Ms400),
+    (Gain::X2, IntegrationTime::Ms800),
+];
+
+
+/// Count limits used to move between [`STEPS`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AutoRange {
+    /// Below this many counts the sensitivity is raised.
+    pub low: u16,
+    /// At or above this many counts the sensitivity is lowered.
+    pub high: u16,
+}
+
+impl Default for AutoRange {
+    fn default() -> Self {
+        AutoRange { low: 100, high: 10000 }
+    }
+}
+
+impl AutoRange {
+    /// Index of the step whose resolution is closest to the given settings.
+    pub fn step_for(gain: Gain, integration_time: IntegrationTime) -> usize {
+        let target = resolution_factor(gain, integration_time);
+        let mut best = 0;
+        for (index, (step_gain, step_time)) in STEPS.iter().enumerate() {
+            let distance = (resolution_factor(*step_gain, *step_time) - target).abs();
+            let best_distance = (resolution_factor(STEPS[best].0, STEPS[best].1) - target).abs();
+            if distance < best_distance {
+                best = index;
+            }
+        }
+        best
+    }
+
+    /// Step to move to after reading `raw` counts at `step`, or `None` to stay.
+    ///
+    /// A move is only taken if the counts expected at the new step land
+    /// inside the `low..high` window, so a reading never bounces back.
+    pub fn next_step(&self, step: usize, raw: u16) -> Option<usize> {
+        let saturated = raw == u16::MAX;
+        if (raw >= self.high || saturated) && step > 0 {
+            let expected = f64::from(raw) * ratio(step, step - 1);
+            if saturated || expected >= f64::from(self.low) {
+                return Some(step - 1);
+            }
+        } else if raw < self.low && step + 1 < STEPS.len() {
+            let expected = f64::from(raw) * ratio(step, step + 1);
+            if expected < f64::from(self.high) {
+                return Some(step + 1);
+            }
+        }
+        None
+    }
+}
+
+
+/// Factor applied to the counts when moving from one step to another.
+fn ratio(from: usize, to: usize) -> f64 {
+    let (from_gain, from_time) = STEPS[from];
+    let (to_gain, to_time) = STEPS[to];
+    resolution_factor(from_gain, from_time) / resolution_factor(to_gain, to_time)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raises_sensitivity_in_the_dark() {
+        let range = AutoRange::default();
+        assert_eq!(range.next_step(3, 20), Some(4));
+        assert_eq!(range.next_step(8, 20), None);
+    }
+
+    #[test]
+    fn lowers_sensitivity_near_saturation() {
+        let range = AutoRange::default();
+        assert_eq!(range.next_step(5, 12000), Some(4));
+        assert_eq!(range.next_step(5, u16::MAX), Some(4));
+        assert_eq!(range.next_step(0, u16::MAX), None);
+    }
+
+    #[test]
+    fn does_not_oscillate() {
+        // 3000 counts would saturate after quadrupling, so the range holds.
+        let range = AutoRange { low: 5000, high: 10000 };
+        assert_eq!(range.next_step(3, 3000), None);
+        assert_eq!(range.next_step(5, 10500), Some(4));
+        let range = AutoRange { low: 100, high: 150 };
+        assert_eq!(range.next_step(5, 160), None);
+    }
+
+    #[test]
+    fn finds_closest_step() {
+        assert_eq!(AutoRange::step_for(Gain::X1_4, IntegrationTime::Ms50), 2);
+        assert_eq!(AutoRange::step_for(Gain::X2, IntegrationTime::Ms800), 8);
+    }
+}
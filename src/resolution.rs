@@ -0,0 +1,152 @@
+//! Bus-free conversion between raw counts and lux.
+
+// Public imports
+use core::marker::PhantomData;
+
+// Local imports
+use crate::calibration::Calibration;
+use crate::i2c::{Gain, IntegrationTime};
+use crate::variant::{ChipVariant, Veml7700};
+
+
+/// Scale of the part `V` at one gain and integration time, together with
+/// the [`Calibration`] applied to its readings.
+///
+/// Every method is a `const fn`, so tables and thresholds can be worked out
+/// at compile time, and logged counts converted without a device.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Resolution<V = Veml7700> {
+    gain: Gain,
+    integration_time: IntegrationTime,
+    calibration: Calibration,
+    variant: PhantomData<V>,
+}
+
+impl Resolution {
+    pub const fn new(gain: Gain, integration_time: IntegrationTime) -> Self {
+        Self::for_variant(gain, integration_time)
+    }
+}
+
+impl<V: ChipVariant> Resolution<V> {
+    /// Scale of the part `V`, e.g. `Resolution::<Veml6035>::for_variant(..)`.
+    pub const fn for_variant(gain: Gain, integration_time: IntegrationTime) -> Self {
+        Resolution { gain, integration_time, calibration: Calibration::for_variant::<V>(), variant: PhantomData }
+    }
+
+    /// Replace the datasheet calibration of the part.
+    pub const fn with_calibration(mut self, calibration: Calibration) -> Self {
+        self.calibration = calibration;
+        self
+    }
+
+    pub const fn calibration(&self) -> Calibration {
+        self.calibration
+    }
+
+    pub const fn gain(&self) -> Gain {
+        self.gain
+    }
+
+    pub const fn integration_time(&self) -> IntegrationTime {
+        self.integration_time
+    }
+
+    /// Lux represented by one raw count.
+    pub const fn lux_per_count(&self) -> f64 {
+        V::RESOLUTION * resolution_factor(self.gain, self.integration_time)
+    }
+
+    /// Convert a raw ambient light count to calibrated lux.
+    pub const fn lux(&self, raw: u16) -> f32 {
+        self.calibration.apply(self.lux_per_count() * raw as f64) as f32
+    }
+
+    /// Whether the non-linearity correction applies to `raw`.
+    pub const fn is_corrected(&self, raw: u16) -> bool {
+        self.calibration.corrects(self.lux_per_count() * raw as f64)
+    }
+
+    /// Lux at the top count.
+    pub const fn max_lux(&self) -> f32 {
+        self.lux(u16::MAX)
+    }
+
+    /// Smallest raw count that converts to at least `lux`, or `None` if `lux`
+    /// is negative or beyond full scale.
+    pub const fn raw_for_lux(&self, lux: f32) -> Option<u16> {
+        if lux.is_nan() || lux < 0.0 || lux > self.max_lux() {
+            return None;
+        }
+
+        // The conversion never decreases, so a binary search inverts it
+        // exactly, calibration included.
+        let (mut low, mut high) = (0u16, u16::MAX);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            if self.lux(middle) < lux {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        Some(low)
+    }
+}
+
+
+/// Lux per count relative to gain x2 and 100 ms, the same on every part.
+pub(crate) const fn resolution_factor(gain: Gain, integration_time: IntegrationTime) -> f64 {
+    let it_factor: f64 = match integration_time {
+        IntegrationTime::Ms25 => 4.0,
+        IntegrationTime::Ms50 => 2.0,
+        IntegrationTime::Ms100 => 1.0,
+        IntegrationTime::Ms200 => 0.5,
+        IntegrationTime::Ms400 => 0.25,
+        IntegrationTime::Ms800 => 0.125
+    };
+
+    let gain_factor: f64 = match gain {
+        Gain::X2 => 1.0,
+        Gain::X1 => 2.0,
+        Gain::X1_4 => 8.0,
+        Gain::X1_8 => 16.0
+    };
+
+    it_factor * gain_factor
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variant::Veml6035;
+
+    const DIM: Resolution = Resolution::new(Gain::X2, IntegrationTime::Ms800);
+    const THRESHOLD: Option<u16> = DIM.raw_for_lux(100.0);
+
+    #[test]
+    fn usable_in_const() {
+        assert_eq!(DIM.lux_per_count(), 0.0042);
+        assert_eq!(THRESHOLD, Some(23810));
+        assert_eq!(DIM.raw_for_lux(DIM.max_lux() * 2.0), None);
+        assert_eq!(DIM.raw_for_lux(-1.0), None);
+    }
+
+    #[test]
+    fn inverse_of_lux() {
+        let resolution = Resolution::new(Gain::X1_8, IntegrationTime::Ms25);
+        for raw in [0, 1, 500, 30000, u16::MAX] {
+            assert_eq!(resolution.raw_for_lux(resolution.lux(raw)), Some(raw));
+        }
+        assert_eq!(Resolution::<Veml6035>::for_variant(Gain::X2, IntegrationTime::Ms100).lux(1000), 6.4);
+    }
+
+    #[test]
+    fn applies_calibration() {
+        let behind_glass = Calibration { scale: 0.5, ..Calibration::default() };
+        let resolution = DIM.with_calibration(behind_glass);
+        assert_eq!(resolution.lux(1000), 8.4);
+        assert_eq!(resolution.raw_for_lux(8.4), Some(1000));
+    }
+}
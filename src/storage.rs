@@ -0,0 +1,230 @@
+//! Compact, versioned byte encoding of settings for storage in flash.
+//!
+//! Each record starts with a format version, followed by little-endian
+//! fields. The layout does not depend on the chip variant, nor on serde,
+//! so it stays available to builds without the `serde` feature.
+//!
+//! With `serde`, any of the crate's types can instead be wrapped in a
+//! [`Versioned`] envelope before handing it to postcard, JSON or TOML.
+
+// Public imports
+use core::fmt::{Display, Formatter};
+
+// Local imports
+use crate::calibration::Calibration;
+use crate::config::Config;
+use crate::i2c::{Gain, IntegrationTime, InterruptEnable, PersistenceProtectNumber, PowerSavingMode, PowerSavingModeEnable, Shutdown};
+
+
+const CONFIG_VERSION: u8 = 1;
+const CALIBRATION_VERSION: u8 = 1;
+
+/// Version of the serde representation of the crate's types, raised
+/// whenever one of them changes shape.
+#[cfg(feature = "serde")]
+pub const SERDE_VERSION: u8 = 1;
+
+
+/// A stored record could not be decoded.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StorageError {
+    /// The record is shorter than its format requires.
+    Length,
+    /// The record was written by an unknown format version.
+    Version(u8),
+    /// The byte at `offset` does not map to a known setting.
+    InvalidValue { offset: usize, value: u8 },
+}
+
+impl Display for StorageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Length => write!(f, "record too short"),
+            Self::Version(version) => write!(f, "unknown record version {}", version),
+            Self::InvalidValue { offset, value } => write!(f, "invalid value {:#04x} at offset {}", value, offset),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for StorageError {}
+
+
+impl Config {
+    /// Length of [`to_bytes`](Self::to_bytes).
+    pub const ENCODED_LEN: usize = 12;
+
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_LEN] {
+        let [high_low, high_high] = self.high_threshold.to_le_bytes();
+        let [low_low, low_high] = self.low_threshold.to_le_bytes();
+        [
+            CONFIG_VERSION,
+            u16::from(self.gain) as u8,
+            u16::from(self.integration_time) as u8,
+            u16::from(self.persist_protect_number) as u8,
+            u16::from(self.interrupt_enable) as u8,
+            u16::from(self.shutdown) as u8,
+            u16::from(self.power_saving_mode) as u8,
+            u16::from(self.power_saving_mode_enable) as u8,
+            high_low, high_high,
+            low_low, low_high,
+        ]
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, StorageError> {
+        let bytes: &[u8; Self::ENCODED_LEN] = bytes.get(..Self::ENCODED_LEN).and_then(|bytes| bytes.try_into().ok()).ok_or(StorageError::Length)?;
+        if bytes[0] != CONFIG_VERSION {
+            return Err(StorageError::Version(bytes[0]));
+        }
+
+        Ok(Config {
+            gain: setting::<Gain>(bytes, 1)?,
+            integration_time: setting::<IntegrationTime>(bytes, 2)?,
+            persist_protect_number: setting::<PersistenceProtectNumber>(bytes, 3)?,
+            interrupt_enable: setting::<InterruptEnable>(bytes, 4)?,
+            shutdown: setting::<Shutdown>(bytes, 5)?,
+            power_saving_mode: setting::<PowerSavingMode>(bytes, 6)?,
+            power_saving_mode_enable: setting::<PowerSavingModeEnable>(bytes, 7)?,
+            high_threshold: u16::from_le_bytes([bytes[8], bytes[9]]),
+            low_threshold: u16::from_le_bytes([bytes[10], bytes[11]]),
+        })
+    }
+}
+
+
+impl Calibration {
+    /// Length of [`to_bytes`](Self::to_bytes).
+    pub const ENCODED_LEN: usize = 58;
+
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut bytes = [0u8; Self::ENCODED_LEN];
+        bytes[0] = CALIBRATION_VERSION;
+        bytes[1] = u8::from(self.polynomial.is_some());
+        let polynomial = self.polynomial.unwrap_or_default();
+        let values = [polynomial[0], polynomial[1], polynomial[2], polynomial[3], self.correct_above, self.scale, self.dark_offset];
+        for (chunk, value) in bytes[2..].chunks_exact_mut(8).zip(values) {
+            chunk.copy_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, StorageError> {
+        let bytes = bytes.get(..Self::ENCODED_LEN).ok_or(StorageError::Length)?;
+        if bytes[0] != CALIBRATION_VERSION {
+            return Err(StorageError::Version(bytes[0]));
+        }
+
+        let mut values = [0.0f64; 7];
+        for (value, chunk) in values.iter_mut().zip(bytes[2..].chunks_exact(8)) {
+            let mut raw = [0u8; 8];
+            raw.copy_from_slice(chunk);
+            *value = f64::from_le_bytes(raw);
+        }
+        let polynomial = match bytes[1] {
+            0 => None,
+            1 => Some([values[0], values[1], values[2], values[3]]),
+            value => return Err(StorageError::InvalidValue { offset: 1, value }),
+        };
+
+        Ok(Calibration { polynomial, correct_above: values[4], scale: values[5], dark_offset: values[6] })
+    }
+}
+
+
+/// Serde envelope tagging a payload with [`SERDE_VERSION`].
+#[cfg(feature = "serde")]
+#[derive(Clone, Copy, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Versioned<T> {
+    pub version: u8,
+    pub payload: T,
+}
+
+#[cfg(feature = "serde")]
+impl<T> Versioned<T> {
+    pub fn new(payload: T) -> Self {
+        Versioned { version: SERDE_VERSION, payload }
+    }
+
+    /// The payload, if it was written by the current serde representation.
+    pub fn into_payload(self) -> Result<T, StorageError> {
+        if self.version != SERDE_VERSION {
+            return Err(StorageError::Version(self.version));
+        }
+
+        Ok(self.payload)
+    }
+}
+
+
+fn setting<T: TryFrom<u16>>(bytes: &[u8], offset: usize) -> Result<T, StorageError> {
+    let value = bytes[offset];
+    T::try_from(u16::from(value)).map_err(|_| StorageError::InvalidValue { offset, value })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_round_trip() {
+        let config = Config::builder()
+            .gain(Gain::X1_8)
+            .integration_time(IntegrationTime::Ms400)
+            .high_threshold(4000)
+            .low_threshold(200)
+            .build();
+
+        let bytes = config.to_bytes();
+        assert_eq!(bytes, [1, 2, 2, 0, 0, 0, 0, 0, 0xA0, 0x0F, 0xC8, 0x00]);
+        assert_eq!(Config::from_bytes(&bytes), Ok(config));
+        assert_eq!(Config::from_bytes(&bytes[..4]), Err(StorageError::Length));
+
+        let mut corrupt = bytes;
+        corrupt[2] = 9;
+        assert_eq!(Config::from_bytes(&corrupt), Err(StorageError::InvalidValue { offset: 2, value: 9 }));
+        corrupt[0] = 2;
+        assert_eq!(Config::from_bytes(&corrupt), Err(StorageError::Version(2)));
+    }
+
+    #[test]
+    fn calibration_round_trip() {
+        let calibration = Calibration { scale: 0.8, dark_offset: 0.25, ..Calibration::default() };
+        assert_eq!(Calibration::from_bytes(&calibration.to_bytes()), Ok(calibration));
+
+        let linear = Calibration { polynomial: None, ..calibration };
+        assert_eq!(Calibration::from_bytes(&linear.to_bytes()), Ok(linear));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn versioned_postcard_round_trip() {
+        use crate::{Address, AutoRange, Measurement, Resolution};
+
+        let measurement = Measurement::new(&Resolution::new(Gain::X1, IntegrationTime::Ms100), 1000, Some(2000), false);
+        let mut buffer = [0u8; 64];
+        let bytes = postcard::to_slice(&Versioned::new(measurement), &mut buffer).unwrap();
+        assert_eq!(bytes[0], SERDE_VERSION);
+        let decoded: Versioned<Measurement> = postcard::from_bytes(bytes).unwrap();
+        assert_eq!(decoded.into_payload(), Ok(measurement));
+
+        let bytes = postcard::to_slice(&Versioned::new((Address::Custom(0x2A), AutoRange::default())), &mut buffer).unwrap();
+        let decoded: Versioned<(Address, AutoRange)> = postcard::from_bytes(bytes).unwrap();
+        assert_eq!(decoded.into_payload(), Ok((Address::Custom(0x2A), AutoRange::default())));
+
+        // Addresses are stored as the 7-bit value and checked on the way back.
+        assert_eq!(bytes[1], 0x2A);
+        bytes[1] = 0x7F;
+        let decoded: Versioned<(Address, AutoRange)> = postcard::from_bytes(bytes).unwrap();
+        assert_eq!(decoded.payload.0, Address::Custom(0x7F));
+        bytes[1] = 0x80;
+        assert!(postcard::from_bytes::<Versioned<(Address, AutoRange)>>(bytes).is_err());
+
+        bytes[1] = 0x2A;
+        bytes[0] = SERDE_VERSION + 1;
+        let decoded: Versioned<(Address, AutoRange)> = postcard::from_bytes(bytes).unwrap();
+        assert_eq!(decoded.into_payload(), Err(StorageError::Version(SERDE_VERSION + 1)));
+    }
+}
@@ -0,0 +1,127 @@
+//! Supported parts of the Vishay ambient light sensor family.
+//!
+//! The parts share the register map, but differ in resolution, in how the
+//! gain is laid out in the configuration register, and in whether the
+//! high-lux non-linearity correction applies.
+
+// Local imports
+use crate::i2c::bitfield::{self, Field};
+use crate::i2c::constants::registers;
+use crate::i2c::{DecodeError, Gain};
+
+
+/// Per-part register layout and conversion data.
+pub trait ChipVariant {
+    /// Lux per count at gain x2 and 100 ms integration time.
+    const RESOLUTION: f64;
+    /// Coefficients `C0..=C3` of the high-lux correction polynomial, if the part needs one.
+    const CORRECTION: Option<[f64; 4]>;
+    /// Part code in the low byte of the ID register.
+    const DEVICE_ID: u8;
+
+    /// Replace the gain inside the configuration register word.
+    fn encode_gain(state: u16, gain: Gain) -> u16;
+
+    /// Decode the gain out of the configuration register word.
+    fn decode_gain(state: u16) -> Result<Gain, DecodeError>;
+}
+
+
+/// Coefficients published in Vishay's application notes for VEML7700 and VEML6030.
+const VEML7700_CORRECTION: [f64; 4] = [1.0023, 8.1488e-05, -9.3924e-09, 6.0135e-13];
+
+
+/// VEML7700, 0.0042 lx/count at its most sensitive setting.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Veml7700;
+
+impl ChipVariant for Veml7700 {
+    const RESOLUTION: f64 = 0.0336;
+    const CORRECTION: Option<[f64; 4]> = Some(VEML7700_CORRECTION);
+    const DEVICE_ID: u8 = 0x81;
+
+    fn encode_gain(state: u16, gain: Gain) -> u16 {
+        bitfield::GAIN.encode(state, gain)
+    }
+
+    fn decode_gain(state: u16) -> Result<Gain, DecodeError> {
+        bitfield::GAIN.decode(state)
+    }
+}
+
+
+/// VEML6030, 0.0036 lx/count at its most sensitive setting.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Veml6030;
+
+impl ChipVariant for Veml6030 {
+    const RESOLUTION: f64 = 0.0288;
+    const CORRECTION: Option<[f64; 4]> = Some(VEML7700_CORRECTION);
+    const DEVICE_ID: u8 = 0x81;
+
+    fn encode_gain(state: u16, gain: Gain) -> u16 {
+        bitfield::GAIN.encode(state, gain)
+    }
+
+    fn decode_gain(state: u16) -> Result<Gain, DecodeError> {
+        bitfield::GAIN.decode(state)
+    }
+}
+
+
+/// VEML6035, with separate gain, double-gain and sensitivity bits, and a
+/// linear response that needs no correction.
+///
+/// The 1/8 gains map to low sensitivity; the double-gain bit is kept clear.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Veml6035;
+
+const VEML6035_SENSITIVITY: Field = Field { register: registers::SETTING_REG, trailing_zeros: 12, length: 1 };
+const VEML6035_DOUBLE_GAIN: Field = Field { register: registers::SETTING_REG, trailing_zeros: 11, length: 1 };
+const VEML6035_GAIN: Field = Field { register: registers::SETTING_REG, trailing_zeros: 10, length: 1 };
+
+impl ChipVariant for Veml6035 {
+    const RESOLUTION: f64 = 0.0064;
+    const CORRECTION: Option<[f64; 4]> = None;
+    const DEVICE_ID: u8 = 0x35;
+
+    fn encode_gain(state: u16, gain: Gain) -> u16 {
+        let (low_sensitivity, double) = match gain {
+            Gain::X1 => (0u16, 0u16),
+            Gain::X2 => (0, 1),
+            Gain::X1_8 => (1, 0),
+            Gain::X1_4 => (1, 1),
+        };
+        let state = VEML6035_SENSITIVITY.encode(state, low_sensitivity);
+        let state = VEML6035_DOUBLE_GAIN.encode(state, 0u16);
+        VEML6035_GAIN.encode(state, double)
+    }
+
+    fn decode_gain(state: u16) -> Result<Gain, DecodeError> {
+        let bits = |field: Field| (state >> field.trailing_zeros) & 1;
+        match (bits(VEML6035_SENSITIVITY), bits(VEML6035_DOUBLE_GAIN), bits(VEML6035_GAIN)) {
+            (0, 0, 0) => Ok(Gain::X1),
+            (0, 0, 1) => Ok(Gain::X2),
+            (1, 0, 0) => Ok(Gain::X1_8),
+            (1, 0, 1) => Ok(Gain::X1_4),
+            _ => Err(DecodeError { register: registers::SETTING_REG, value: state }),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn veml6035_gain_layout() {
+        for gain in [Gain::X1, Gain::X2, Gain::X1_8, Gain::X1_4] {
+            let state = Veml6035::encode_gain(0x0800, gain);
+            assert_eq!(state & 0x0800, 0);
+            assert_eq!(Veml6035::decode_gain(state), Ok(gain));
+        }
+        assert_eq!(Veml6035::encode_gain(0, Gain::X1_4), 0x1400);
+        assert!(Veml6035::decode_gain(0x0800).is_err());
+    }
+}